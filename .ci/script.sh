@@ -6,6 +6,10 @@ set -ex
 main() {
     cross build --target $TARGET --release --all-features
 
+    if [ $TARGET = x86_64-unknown-linux-gnu ]; then
+        cargo clippy --all-targets --all-features -- -D warnings
+    fi
+
     if [ ! -z $DISABLE_TESTS ]; then
         return
     fi
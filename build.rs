@@ -0,0 +1,8 @@
+use std::env;
+
+fn main() {
+    // error_chain, used by the binaries only, generates code checking this cfg
+    if env::var_os("CARGO_FEATURE_ERROR_CHAIN").is_some() {
+        println!("cargo::rustc-check-cfg=cfg(has_error_description_deprecated)");
+    }
+}
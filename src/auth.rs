@@ -0,0 +1,310 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bcrypt;
+use config::{BasicAuth, OidcAuth, ProxyAuth, User};
+use futures::{Future, Stream};
+use hyper::http;
+use hyper::{self, Body, Client, Request, StatusCode};
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use hyper_rustls::HttpsConnector;
+use rand::distributions::Alphanumeric;
+use rand::{self, Rng};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use serde_path_to_error;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+use std::net::IpAddr;
+use std::str::{self, FromStr};
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
+use tokio_core::reactor::Core;
+use url::{self, Url};
+use url::form_urlencoded::{self, byte_serialize};
+
+/// Returns the user name if the value of an `Authorization` header carries the credentials of a
+/// configured user.
+pub fn verify_basic(basic: &BasicAuth, authorization: &str) -> Option<String> {
+    let encoded = authorization.strip_prefix("Basic ")?;
+    let decoded = BASE64.decode(encoded.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (name, password) = credentials.split_once(':')?;
+
+    basic
+        .users
+        .iter()
+        .find(|user| user.name == name)
+        .filter(|user| verify_password(user, password))
+        .map(|user| user.name.to_string())
+}
+
+/// Number of successful password verifications to remember; the cache is cleared when full.
+const VERIFIED_CREDENTIALS_LIMIT: usize = 1024;
+
+lazy_static! {
+    static ref CREDENTIALS_SALT: String = random_state();
+    /// Salted digests of credentials bcrypt accepted; bcrypt is deliberately too slow to run for
+    /// each request a browser sends with the same credentials.
+    static ref VERIFIED_CREDENTIALS: Mutex<HashSet<Vec<u8>>> = Mutex::new(HashSet::new());
+}
+
+fn verify_password(user: &User, password: &str) -> bool {
+    // The hash is part of the digest so that a changed password invalidates the cache entry
+    let digest = Sha256::new()
+        .chain(CREDENTIALS_SALT.as_bytes())
+        .chain(user.name.as_bytes())
+        .chain([0])
+        .chain(password.as_bytes())
+        .chain([0])
+        .chain(user.password_hash.as_bytes())
+        .finalize()
+        .to_vec();
+    let verified = || VERIFIED_CREDENTIALS.lock().unwrap_or_else(PoisonError::into_inner);
+    if verified().contains(&digest) {
+        return true;
+    }
+
+    if !bcrypt::verify(password, &user.password_hash).unwrap_or(false) {
+        return false;
+    }
+    let mut verified = verified();
+    if verified.len() >= VERIFIED_CREDENTIALS_LIMIT {
+        verified.clear();
+    }
+    verified.insert(digest);
+
+    true
+}
+
+/// Returns whether a request from `remote` comes from one of the trusted proxies and may thus
+/// carry the user name in the proxy header.
+pub fn is_trusted_proxy(proxy: &ProxyAuth, remote: IpAddr) -> Result<bool> {
+    for range in &proxy.trusted_proxies {
+        if range.parse::<IpRange>()?.contains(remote) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g., `10.0.0.0/8`; a plain address is a network
+/// of a single host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Returns whether `ip` belongs to this network; IPv4-mapped IPv6 addresses like
+    /// `::ffff:10.0.0.1`, e.g., of peers of a dual-stack socket, count as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix_len)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix_len)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<IpRange> {
+        let invalid = || Error::InvalidIpRange { range: s.to_string() };
+        let (network, prefix_len) = match s.split_once('/') {
+            Some((network, prefix_len)) => (network, Some(prefix_len)),
+            None => (s, None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| invalid())?;
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+            None => max_prefix_len,
+        };
+        if prefix_len > max_prefix_len {
+            return Err(invalid());
+        }
+
+        Ok(IpRange {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// Returns a random value for the `state` parameter protecting the OIDC flow against CSRF.
+pub fn random_state() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Endpoints of an OpenID Connect provider as published by its discovery document.
+#[derive(Debug, Deserialize)]
+pub struct OidcProvider {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+impl OidcProvider {
+    /// Fetches the discovery document from `<issuer>/.well-known/openid-configuration`.
+    pub fn discover(oidc: &OidcAuth) -> Result<OidcProvider> {
+        let url = format!("{}/.well-known/openid-configuration", oidc.issuer.trim_end_matches('/'));
+        let request = Request::get(url.as_str())
+            .header(ACCEPT, "application/json")
+            .body(Body::empty())
+            .map_err(|e| Error::Request { url, source: e })?;
+
+        fetch_json(request)
+    }
+
+    /// Returns the URL of the provider's login page to redirect users to.
+    pub fn authorize_url(&self, oidc: &OidcAuth, state: &str) -> Result<String> {
+        let mut url = Url::parse(&self.authorization_endpoint).map_err(|e| Error::InvalidEndpoint {
+            url: self.authorization_endpoint.to_string(),
+            source: e,
+        })?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &oidc.client_id)
+            .append_pair("redirect_uri", &oidc.redirect_url)
+            .append_pair("scope", &oidc.scopes.join(" "))
+            .append_pair("state", state);
+
+        Ok(url.into())
+    }
+
+    /// Exchanges the authorization code the provider passed to the callback for an access token
+    /// and returns the user name from the user info.
+    pub fn user(&self, oidc: &OidcAuth, code: &str) -> Result<String> {
+        let body = form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "authorization_code")
+            .append_pair("code", code)
+            .append_pair("redirect_uri", &oidc.redirect_url)
+            .finish();
+        // RFC 6749 requires form encoding client id and secret before joining them
+        let credentials = format!(
+            "{}:{}",
+            byte_serialize(oidc.client_id.as_bytes()).collect::<String>(),
+            byte_serialize(oidc.client_secret.as_bytes()).collect::<String>()
+        );
+        let request = Request::post(self.token_endpoint.as_str())
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(AUTHORIZATION, format!("Basic {}", BASE64.encode(credentials)))
+            .body(Body::from(body))
+            .map_err(|e| Error::Request {
+                url: self.token_endpoint.to_string(),
+                source: e,
+            })?;
+        let token: TokenResponse = fetch_json(request)?;
+
+        let request = Request::get(self.userinfo_endpoint.as_str())
+            .header(ACCEPT, "application/json")
+            .header(AUTHORIZATION, format!("Bearer {}", token.access_token))
+            .body(Body::empty())
+            .map_err(|e| Error::Request {
+                url: self.userinfo_endpoint.to_string(),
+                source: e,
+            })?;
+        let user_info: Value = fetch_json(request)?;
+
+        user_info
+            .get(&oidc.username_claim)
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+            .ok_or_else(|| Error::MissingClaim {
+                claim: oidc.username_claim.to_string(),
+            })
+    }
+}
+
+fn fetch_json<T: DeserializeOwned>(request: Request<Body>) -> Result<T> {
+    let url = request.uri().to_string();
+    let mut core = Core::new().map_err(Error::Tokio)?;
+    let client = Client::builder().build::<_, Body>(HttpsConnector::new(1));
+    let call = client.request(request).and_then(|res| {
+        let status = res.status();
+        res.into_body().concat2().map(move |body| (status, body))
+    });
+    let (status, body) = match core.run(call) {
+        Ok(answer) => answer,
+        Err(e) => return Err(Error::Http { url, source: e }),
+    };
+
+    if status != StatusCode::OK {
+        return Err(Error::Status {
+            url,
+            status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        });
+    }
+    let deserializer = &mut serde_json::Deserializer::from_slice(&body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| Error::Json {
+        url,
+        path: e.path().to_string(),
+        source: e.into_inner(),
+    })
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Invalid IP range '{range}'")]
+    InvalidIpRange { range: String },
+
+    #[error("OpenID Connect is not configured")]
+    OidcNotConfigured,
+
+    #[error("Invalid OpenID Connect endpoint '{url}'")]
+    InvalidEndpoint { url: String, source: url::ParseError },
+
+    #[error("Failed to create request for '{url}'")]
+    Request { url: String, source: http::Error },
+
+    #[error("Failed to use Tokio")]
+    Tokio(#[source] io::Error),
+
+    #[error("OpenID Connect request to '{url}' failed")]
+    Http { url: String, source: hyper::Error },
+
+    #[error("OpenID Connect provider answered '{url}' with {status}: {body}")]
+    Status {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+
+    #[error("OpenID Connect provider answered '{url}' with invalid JSON at '{path}'")]
+    Json {
+        url: String,
+        path: String,
+        source: serde_json::Error,
+    },
+
+    #[error("User info lacks claim '{claim}'")]
+    MissingClaim { claim: String },
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
@@ -2,39 +2,249 @@ extern crate ansi_term;
 #[macro_use]
 extern crate error_chain;
 extern crate clap;
-extern crate service_world;
+#[macro_use]
+extern crate log;
+extern crate ratatui;
 extern crate serde_json;
+extern crate service_world;
 extern crate tabwriter;
 
-use ansi_term::Color;
-use clap::{App, Arg};
+use ansi_term::{Color, Style};
+use clap::{App, Arg, ArgMatches, Shell, SubCommand};
+use error_chain::ChainedError;
 use tabwriter::TabWriter;
-use service_world::consul::{Consul, Catalog};
-use std::io::Write;
+use service_world::config::{Config, ConfigFile};
+use service_world::consul::{Consul, Catalog, Node};
+use service_world::discover::{self, Report};
+use service_world::logging;
+use std::fs::{self, File};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
 
 fn run() -> Result<()> {
     let args = build_cli().get_matches();
 
-    let output = args.value_of("output module").ok_or_else(|| {
-        ErrorKind::CliError("Output module not specified".to_string())
-    })?;
-    let url = args.value_of("url").ok_or_else(|| {
-        ErrorKind::CliError("Url not specified".to_string())
+    if let Some(shell) = args.value_of("completions") {
+        return generate_completions(shell);
+    }
+    // Checks report any failure, including invalid settings, as UNKNOWN
+    if let ("check", Some(sub_args)) = args.subcommand() {
+        query::check(&args, sub_args);
+    }
+    init_logging(&args)?;
+
+    let settings = Settings::from_args(&args)?;
+    // Comparing two snapshots is the only mode that does not need Consul
+    if let ("diff", Some(sub_args)) = args.subcommand() {
+        let consul = settings.consul(&args).ok();
+        return snapshot::diff(&args, sub_args, &settings, consul.as_ref());
+    }
+
+    let consul = settings.consul(&args)?;
+    let template = match args.value_of("template") {
+        Some(name) => Some(load_template(name)?),
+        None => None,
+    };
+
+    match args.subcommand() {
+        ("addr", Some(sub_args)) => return query::addr(sub_args, &settings, &consul),
+        ("url", Some(sub_args)) => return query::url(sub_args, &settings, &consul),
+        ("nodes", Some(sub_args)) => return query::nodes(&args, sub_args, &settings, &consul),
+        ("tags", Some(_)) => return query::tags(&settings, &consul),
+        ("snapshot", Some(sub_args)) => return snapshot::save(sub_args, &settings, &consul),
+        _ => {}
+    }
+
+    if args.is_present("tui") {
+        return tui::run(settings, consul, interval(&args)?);
+    }
+
+    if args.is_present("watch") {
+        return watch(&args, &settings, &consul, template.as_deref());
+    }
+
+    let buffer = render(&args, &settings, &consul, template.as_deref())?;
+    if let Some(file) = args.value_of("write to") {
+        write_atomically(Path::new(file), &buffer)
+    } else {
+        std::io::stdout().write_all(&buffer).chain_err(|| ErrorKind::OutputError)
+    }
+}
+
+/// Settings given on the command line falling back to the `[discover]` section of the layered
+/// config and the selected profile.
+struct Settings {
+    config: Config,
+    output: String,
+    services: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+}
+
+impl Settings {
+    fn from_args(args: &ArgMatches) -> Result<Settings> {
+        let config = Config::load_with_profile(config_file(args)?.as_ref(), args.value_of("profile"))?;
+
+        let discover = &config.discover;
+        let output = args
+            .value_of("output module")
+            .map(|x| x.to_string())
+            .or_else(|| discover.output.clone())
+            .unwrap_or_else(|| "terminal".to_string());
+        if !discover::OUTPUT_MODULES.contains(&output.as_str()) {
+            bail!(ErrorKind::CliError(format!("Unknown output module '{}'", output)));
+        }
+        let services = args
+            .values_of_lossy("services")
+            .or_else(|| discover.services.clone());
+        let tags = args
+            .values_of_lossy("tags")
+            .or_else(|| discover.tags.clone());
+
+        Ok(Settings {
+            config,
+            output,
+            services,
+            tags,
+        })
+    }
+
+    fn consul(&self, args: &ArgMatches) -> Result<Consul> {
+        let url = args
+            .value_of("url")
+            .map(|x| x.to_string())
+            .or_else(|| self.config.consul.urls.first().cloned())
+            .ok_or_else(|| ErrorKind::CliError("Url not specified".to_string()))?;
+        let token = args
+            .value_of("token")
+            .map(|x| x.to_string())
+            .or_else(|| self.config.consul.token.clone());
+
+        match token {
+            Some(token) => Ok(Consul::with_token(url, token)),
+            None => Ok(Consul::new(url)),
+        }
+    }
+
+    fn catalog(&self, consul: &Consul) -> Result<Catalog> {
+        consul
+            .catalog_by(self.services.clone(), self.tags.clone())
+            .map_err(|e| e.into())
+    }
+}
+
+fn render(args: &ArgMatches, settings: &Settings, consul: &Consul, template: Option<&str>) -> Result<Vec<u8>> {
+    let catalog = settings.catalog(consul)?;
+
+    let mut buffer = Vec::new();
+    match settings.output.as_str() {
+        "json" => Report::from_catalog(&catalog).write_json(&mut buffer).map_err(|e| e.into()),
+        "yaml" => Report::from_catalog(&catalog).write_yaml(&mut buffer).map_err(|e| e.into()),
+        "csv" => Report::from_catalog(&catalog).write_csv(&mut buffer).map_err(|e| e.into()),
+        "tsv" => Report::from_catalog(&catalog).write_tsv(&mut buffer).map_err(|e| e.into()),
+        "prometheus" => Report::from_catalog(&catalog)
+            .write_file_sd(&mut buffer, args.is_present("healthy only"))
+            .map_err(|e| e.into()),
+        "template" => {
+            let template = template.ok_or_else(|| {
+                ErrorKind::CliError("Template not specified".to_string())
+            })?;
+            Report::from_catalog(&catalog)
+                .render_template(template, &mut buffer)
+                .map_err(|e| e.into())
+        }
+        "details" => terminal_details_output(&mut buffer, &catalog, Colors::from_args(args)),
+        _ => terminal_output(&mut buffer, &catalog, Colors::from_args(args)),
+    }?;
+
+    Ok(buffer)
+}
+
+/// Re-renders the output every interval and only rewrites the file and runs the reload command
+/// if the output changed. Failures to reach Consul or to reload are reported, but do not stop
+/// watching.
+fn watch(args: &ArgMatches, settings: &Settings, consul: &Consul, template: Option<&str>) -> Result<()> {
+    let file = args.value_of("write to").ok_or_else(|| {
+        ErrorKind::CliError("Watch mode requires --write-to".to_string())
     })?;
-    let consul = Consul::new(url.to_string());
-    let catalog = consul.catalog_by(
-        args.values_of_lossy("services"),
-        args.values_of_lossy("tags"),
-    )?;
+    let path = Path::new(file);
+    let interval = interval(args)?;
+
+    let mut last = fs::read(path).ok();
+    loop {
+        match render(args, settings, consul, template) {
+            Ok(ref buffer) if last.as_ref() == Some(buffer) => {}
+            Ok(buffer) => {
+                write_atomically(path, &buffer)?;
+                info!("Wrote changed output to {}", file);
+                if let Some(command) = args.value_of("exec") {
+                    if let Err(e) = run_command(command) {
+                        error!("{}", e.display_chain());
+                    }
+                }
+                last = Some(buffer);
+            }
+            Err(e) => error!("{}", e.display_chain()),
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn interval(args: &ArgMatches) -> Result<Duration> {
+    args.value_of("interval")
+        .unwrap_or("10")
+        .parse::<u64>()
+        .map(Duration::from_secs)
+        .chain_err(|| ErrorKind::CliError("Interval is not a number of seconds".to_string()))
+}
+
+fn run_command(command: &str) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .status()
+        .chain_err(|| ErrorKind::CommandError(command.to_string()))?;
+    if !status.success() {
+        bail!(ErrorKind::CommandError(command.to_string()));
+    }
+
+    Ok(())
+}
 
-    let mut writer = std::io::stdout();
-    match output {
-        "json" => json_output(&mut writer, &catalog),
-        "details" => terminal_details_output(&mut writer, &catalog),
-        _ => terminal_output(&mut writer, &catalog),
+fn init_logging(args: &ArgMatches) -> Result<()> {
+    let format = args.value_of("log format").unwrap_or("text").parse()?;
+    logging::init(args.occurrences_of("verbose"), format)?;
+
+    Ok(())
+}
+
+/// Returns the config file given on the command line with its format given explicitly or by its
+/// extension.
+fn config_file(args: &ArgMatches) -> Result<Option<ConfigFile>> {
+    let path = match args.value_of("config") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    match args.value_of("config format") {
+        Some(format) => Ok(Some(ConfigFile::with_format(path, format.parse()?))),
+        None => Ok(Some(ConfigFile::new(path))),
     }
 }
 
+fn load_template(name: &str) -> Result<String> {
+    if let Some(template) = discover::builtin_template(name) {
+        return Ok(template.to_string());
+    }
+
+    fs::read_to_string(name).chain_err(|| {
+        ErrorKind::CliError(format!("Could not read template '{}'", name))
+    })
+}
+
 fn build_cli() -> App<'static, 'static> {
     let name = "sw-discover";
     let version = env!("CARGO_PKG_VERSION");
@@ -44,9 +254,8 @@ fn build_cli() -> App<'static, 'static> {
         .arg(
             Arg::with_name("url")
                 .index(1)
-                .required(true)
                 .conflicts_with("completions")
-                .help("URL of consul agent to retrieve catalog from"),
+                .help("URL of consul agent to retrieve catalog from; defaults to consul.urls from config"),
         )
         .arg(
             Arg::with_name("services")
@@ -75,30 +284,236 @@ fn build_cli() -> App<'static, 'static> {
                 .long("output")
                 .short("o")
                 .takes_value(true)
-                .default_value("terminal")
-                .possible_values(&["terminal", "details", "json"])
-                .help("Selects output module"),
+                .possible_values(discover::OUTPUT_MODULES)
+                .help("Selects output module; defaults to discover.output from config or terminal"),
+        )
+        .arg(
+            Arg::with_name("template")
+                .long("template")
+                .takes_value(true)
+                .required_if("output module", "template")
+                .help("Handlebars template file or built-in template haproxy, nginx, or hosts"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .default_value("auto")
+                .possible_values(&["auto", "always", "never"])
+                .help("Colors terminal output; auto honors NO_COLOR and colors only terminals"),
+        )
+        .arg(
+            Arg::with_name("healthy only")
+                .long("healthy-only")
+                .help("Includes only healthy nodes in prometheus output and nodes subcommand"),
+        )
+        .arg(
+            Arg::with_name("write to")
+                .value_name("file")
+                .long("write-to")
+                .takes_value(true)
+                .help("Writes output atomically to file instead of stdout"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .requires("write to")
+                .help("Re-renders output periodically and rewrites file only on changes"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .value_name("seconds")
+                .long("interval")
+                .takes_value(true)
+                .default_value("10")
+                .help("Sets interval between catalog updates in watch and TUI mode"),
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .conflicts_with_all(&["watch", "write to"])
+                .help("Shows interactive dashboard"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .takes_value(true)
+                .help("Sets config file overriding the system and user config files"),
+        )
+        .arg(
+            Arg::with_name("config format")
+                .value_name("format")
+                .long("config-format")
+                .takes_value(true)
+                .requires("config")
+                .possible_values(&["toml", "yaml", "json"])
+                .help("Sets format of config file instead of guessing it from the extension"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .help("Applies the named profile from the config file"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .help("Sets Consul ACL token; defaults to consul.token from config"),
+        )
+        .arg(
+            Arg::with_name("exec")
+                .value_name("command")
+                .long("exec")
+                .takes_value(true)
+                .requires("watch")
+                .help("Runs command after output file has changed in watch mode"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Increases log verbosity; -vv logs Consul requests"),
+        )
+        .arg(
+            Arg::with_name("log format")
+                .value_name("format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .help("Sets format of log messages on stderr [default: text]"),
         )
         .arg(
             Arg::with_name("completions")
                 .long("completions")
                 .takes_value(true)
                 .hidden(true)
-                .possible_values(&["bash", "fish", "zsh"])
+                .possible_values(&["bash", "fish", "zsh", "powershell", "elvish"])
                 .help("The shell to generate the script for"),
         )
+        .subcommand(
+            SubCommand::with_name("addr")
+                .about("Prints host:port of healthy instances of a service")
+                .arg(Arg::with_name("service").required(true).help("Service name")),
+        )
+        .subcommand(
+            SubCommand::with_name("url")
+                .about("Prints links of a service generated from the [services] config section")
+                .arg(Arg::with_name("service").required(true).help("Service name"))
+                .arg(Arg::with_name("link").help("Prints only the link with this name")),
+        )
+        .subcommand(
+            SubCommand::with_name("nodes")
+                .about("Prints nodes of a service")
+                .arg(Arg::with_name("service").required(true).help("Service name")),
+        )
+        .subcommand(SubCommand::with_name("tags").about("Prints all tags"))
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Evaluates health checks and exits with Nagios plugin status codes")
+                .arg(
+                    Arg::with_name("min passing")
+                        .value_name("service:critical[:warning]")
+                        .long("min-passing")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Requires service to have at least this many passing instances"),
+                )
+                .arg(
+                    Arg::with_name("no critical")
+                        .value_name("tag")
+                        .long("no-critical")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Requires no instance of services with this tag to be critical"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Saves catalog to a file for later comparison")
+                .arg(
+                    Arg::with_name("out")
+                        .value_name("file")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Sets snapshot file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Shows changes between two snapshots or a snapshot and the live catalog")
+                .arg(Arg::with_name("from").required(true).help("Snapshot file to compare from"))
+                .arg(Arg::with_name("to").help("Snapshot file to compare to; defaults to live catalog")),
+        )
+}
+
+fn generate_completions(shell: &str) -> Result<()> {
+    let shell = shell.parse::<Shell>().map_err(ErrorKind::CliError)?;
+    build_cli().gen_completions_to("sw-discover", shell, &mut io::stdout());
+
+    Ok(())
+}
+
+/// Decides whether terminal output is colored.
+#[derive(Clone, Copy)]
+struct Colors {
+    enabled: bool,
+}
+
+impl Colors {
+    /// `auto` colors only if stdout is a terminal and `NO_COLOR` is not set, cf. https://no-color.org.
+    fn from_args(args: &ArgMatches) -> Colors {
+        let enabled = match args.value_of("color") {
+            Some("always") => true,
+            Some("never") => false,
+            _ => {
+                env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+                    && !args.is_present("write to")
+                    && io::stdout().is_terminal()
+            }
+        };
+
+        Colors { enabled }
+    }
+
+    fn style(self, color: Color) -> Style {
+        if self.enabled {
+            color.normal()
+        } else {
+            Style::default()
+        }
+    }
+}
+
+/// Writes to a temporary file next to `path` and renames it so readers never see partial output.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        ErrorKind::CliError(format!("'{}' is not a file", path.display()))
+    })?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = File::create(&tmp_path).chain_err(|| ErrorKind::OutputError)?;
+    file.write_all(content).chain_err(|| ErrorKind::OutputError)?;
+    file.sync_all().chain_err(|| ErrorKind::OutputError)?;
+    fs::rename(&tmp_path, path).chain_err(|| ErrorKind::OutputError)
 }
 
-fn terminal_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
+fn terminal_output(w: &mut dyn Write, catalog: &Catalog, colors: Colors) -> Result<()> {
     let mut tw = TabWriter::new(vec![]).padding(1);
     for service_name in catalog.services() {
         let _ = writeln!(
             &mut tw,
             "Service '{}' tagged with {}",
-            Color::Yellow.paint(format!("{}", service_name)),
-            Color::Blue.paint(format!(
+            colors.style(Color::Yellow).paint(service_name.to_string()),
+            colors.style(Color::Blue).paint(format!(
                 "{:?}",
-                catalog.service_tags(service_name).unwrap_or_else(Vec::new)
+                catalog.service_tags(service_name).unwrap_or_default()
             ))
         );
 
@@ -108,9 +523,9 @@ fn terminal_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
         {
             let (node_name, health_indicator) =
                 if catalog.is_node_healthy_for_service(node, service_name) {
-                    (Color::Green.paint(format!("{}", node.name)), ":-)")
+                    (colors.style(Color::Green).paint(node.name.to_string()), ":-)")
                 } else {
-                    (Color::Red.paint(format!("{}", node.name)), ":-(")
+                    (colors.style(Color::Red).paint(node.name.to_string()), ":-(")
                 };
 
             let _ =
@@ -121,10 +536,10 @@ fn terminal_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
                         health_indicator,
                         node.address,
                         node.service_port,
-                        Color::Blue.paint(format!("{:?}", node.service_tags)),
+                        colors.style(Color::Blue).paint(format!("{:?}", node.service_tags)),
                     );
         }
-        let _ = writeln!(&mut tw, "");
+        let _ = writeln!(&mut tw);
     }
 
     let out_str = String::from_utf8(tw.into_inner().chain_err(|| ErrorKind::OutputError)?)
@@ -132,16 +547,16 @@ fn terminal_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
     write!(w, "{}", out_str).chain_err(|| ErrorKind::OutputError)
 }
 
-fn terminal_details_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
+fn terminal_details_output(w: &mut dyn Write, catalog: &Catalog, colors: Colors) -> Result<()> {
     let mut tw = TabWriter::new(vec![]).padding(1);
     for service_name in catalog.services() {
         let _ = writeln!(
             &mut tw,
             "Service '{}' tagged with {}",
-            Color::Yellow.paint(format!("{}", service_name)),
-            Color::Blue.paint(format!(
+            colors.style(Color::Yellow).paint(service_name.to_string()),
+            colors.style(Color::Blue).paint(format!(
                 "{:?}",
-                catalog.service_tags(service_name).unwrap_or_else(Vec::new)
+                catalog.service_tags(service_name).unwrap_or_default()
             ))
         );
 
@@ -151,9 +566,9 @@ fn terminal_details_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
             {
                 let (node_name, health_indicator) =
                     if catalog.is_node_healthy_for_service(node, service_name) {
-                        (Color::Green.paint(format!("{}", node.name)), "up")
+                        (colors.style(Color::Green).paint(node.name.to_string()), "up")
                     } else {
-                        (Color::Red.paint(format!("{}", node.name)), "DOWN")
+                        (colors.style(Color::Red).paint(node.name.to_string()), "DOWN")
                     };
 
                 let _ =
@@ -168,25 +583,25 @@ fn terminal_details_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
                     writeln!(
                         &mut tw,
                         "\t\tmeta:{}",
-                        Color::Blue.paint(format!("{:?}", node.meta_data)),
+                        colors.style(Color::Blue).paint(format!("{:?}", node.meta_data)),
                     );
                 let _ =
                     writeln!(
                         &mut tw,
                         "\t\tip:{}, port:{}",
-                        Color::Cyan.paint(format!("{}", node.address)),
-                        Color::Cyan.paint(format!("{}", node.service_port)),
+                        colors.style(Color::Cyan).paint(node.address.to_string()),
+                        colors.style(Color::Cyan).paint(node.service_port.to_string()),
                     );
                 let _ =
                     writeln!(
                         &mut tw,
                         "\t\tservice_id:{}, service_name:{}, tags:{}",
-                        Color::Yellow.paint(format!("{}", node.service_id)),
-                        Color::Yellow.paint(format!("{}", node.service_name)),
-                        Color::Blue.paint(format!("{:?}", node.service_tags)),
+                        colors.style(Color::Yellow).paint(node.service_id.to_string()),
+                        colors.style(Color::Yellow).paint(node.service_name.to_string()),
+                        colors.style(Color::Blue).paint(format!("{:?}", node.service_tags)),
                     );
             }
-        let _ = writeln!(&mut tw, "");
+        let _ = writeln!(&mut tw);
     }
 
     let out_str = String::from_utf8(tw.into_inner().chain_err(|| ErrorKind::OutputError)?)
@@ -194,8 +609,617 @@ fn terminal_details_output(w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
     write!(w, "{}", out_str).chain_err(|| ErrorKind::OutputError)
 }
 
-fn json_output(mut w: &mut dyn Write, catalog: &Catalog) -> Result<()> {
-    serde_json::to_writer_pretty(&mut w, catalog).chain_err(|| ErrorKind::OutputError)
+/// Subcommands answering targeted questions for scripts; they fail if nothing matches so that
+/// scripts can rely on the exit code.
+mod query {
+    use super::*;
+    use service_world::check::{Status, Summary};
+    use service_world::config::{Checks, MinPassing, NoCritical};
+    use service_world::present;
+    use std::collections::BTreeSet;
+    use std::process;
+
+    pub fn addr(sub_args: &ArgMatches, settings: &Settings, consul: &Consul) -> Result<()> {
+        let service_name = service_name(sub_args)?;
+        let catalog = consul.catalog_by(Some(vec![service_name.to_string()]), settings.tags.clone())?;
+
+        let lines: Vec<_> = sorted_nodes(&catalog, service_name)
+            .into_iter()
+            .filter(|node| catalog.is_node_healthy_for_service(node, service_name))
+            .map(|node| format!("{}:{}", node.address, node.service_port))
+            .collect();
+
+        print_lines(&lines, &format!("healthy instances of service {}", service_name))
+    }
+
+    pub fn url(sub_args: &ArgMatches, settings: &Settings, consul: &Consul) -> Result<()> {
+        let service_name = service_name(sub_args)?;
+        let link = sub_args.value_of("link");
+        let config = &settings.config;
+        let catalog = consul.catalog_by(Some(vec![service_name.to_string()]), settings.tags.clone())?;
+
+        let mut lines = Vec::new();
+        for node in sorted_nodes(&catalog, service_name) {
+            let urls = present::generate_service_urls(config, service_name, node)?;
+            let mut urls: Vec<_> = urls
+                .into_iter()
+                .filter(|(name, _)| link.is_none_or(|x| x == name))
+                .collect();
+            urls.sort();
+            for (name, url) in urls {
+                if link.is_some() {
+                    lines.push(url);
+                } else {
+                    lines.push(format!("{}\t{}\t{}", node.name, name, url));
+                }
+            }
+        }
+
+        print_lines(&lines, &format!("links of service {}", service_name))
+    }
+
+    pub fn nodes(args: &ArgMatches, sub_args: &ArgMatches, settings: &Settings, consul: &Consul) -> Result<()> {
+        let service_name = service_name(sub_args)?;
+        let catalog = consul.catalog_by(Some(vec![service_name.to_string()]), settings.tags.clone())?;
+
+        let lines: Vec<_> = sorted_nodes(&catalog, service_name)
+            .into_iter()
+            .filter(|node| !args.is_present("healthy only") || catalog.is_node_healthy_for_service(node, service_name))
+            .map(|node| node.name.to_string())
+            .collect();
+
+        print_lines(&lines, &format!("nodes of service {}", service_name))
+    }
+
+    pub fn tags(settings: &Settings, consul: &Consul) -> Result<()> {
+        let catalog = settings.catalog(consul)?;
+
+        let tags: BTreeSet<_> = catalog
+            .services()
+            .into_iter()
+            .flat_map(|name| catalog.service_tags(name).unwrap_or_default())
+            .collect();
+        let lines: Vec<_> = tags.into_iter().cloned().collect();
+
+        print_lines(&lines, "tags")
+    }
+
+    /// Prints a Nagios plugin compatible status line and exits with the corresponding exit code.
+    /// Checks come from the `[checks]` section of the config file and the command line.
+    pub fn check(args: &ArgMatches, sub_args: &ArgMatches) -> ! {
+        let summary = init_logging(args).and_then(|_| {
+            let settings = Settings::from_args(args)?;
+            let consul = settings.consul(args)?;
+            let checks = load_checks(sub_args, &settings)?;
+            // The default filters of `[discover]` would hide the very services checked for
+            let catalog = consul.catalog_by(args.values_of_lossy("services"), args.values_of_lossy("tags"))?;
+            Ok(Summary::evaluate(&catalog, &checks))
+        });
+
+        let exit_code = match summary {
+            Ok(summary) => {
+                println!("{}", summary);
+                summary.status.exit_code()
+            }
+            Err(e) => {
+                println!("CHECK {} - {}", Status::Unknown, e);
+                Status::Unknown.exit_code()
+            }
+        };
+        process::exit(exit_code)
+    }
+
+    fn load_checks(sub_args: &ArgMatches, settings: &Settings) -> Result<Checks> {
+        let mut checks = settings.config.checks.clone();
+
+        for value in sub_args.values_of("min passing").into_iter().flatten() {
+            checks.min_passing.push(parse_min_passing(value)?);
+        }
+        for tag in sub_args.values_of("no critical").into_iter().flatten() {
+            checks.no_critical.push(NoCritical { tag: tag.to_string() });
+        }
+
+        Ok(checks)
+    }
+
+    fn parse_min_passing(value: &str) -> Result<MinPassing> {
+        let invalid = || ErrorKind::CliError(format!("Invalid check '{}', expected service:critical[:warning]", value));
+        let parts: Vec<_> = value.split(':').collect();
+        let (service, critical, warning) = match parts.as_slice() {
+            [service, critical] => (service, critical, None),
+            [service, critical, warning] => (service, critical, Some(warning)),
+            _ => bail!(invalid()),
+        };
+        let critical = critical.parse().chain_err(invalid)?;
+        let warning = match warning {
+            Some(warning) => Some(warning.parse().chain_err(invalid)?),
+            None => None,
+        };
+
+        Ok(MinPassing {
+            service: service.to_string(),
+            critical,
+            warning,
+        })
+    }
+
+    fn service_name<'a>(sub_args: &'a ArgMatches) -> Result<&'a str> {
+        sub_args.value_of("service").ok_or_else(|| {
+            ErrorKind::CliError("Service not specified".to_string()).into()
+        })
+    }
+
+    fn sorted_nodes<'a>(catalog: &'a Catalog, service_name: &str) -> Vec<&'a Node> {
+        let mut nodes = catalog.nodes_by_service(service_name).unwrap_or_default();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        nodes
+    }
+
+    fn print_lines(lines: &[String], for_what: &str) -> Result<()> {
+        if lines.is_empty() {
+            bail!(ErrorKind::NoResults(for_what.to_string()));
+        }
+
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        for line in lines {
+            writeln!(w, "{}", line).chain_err(|| ErrorKind::OutputError)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Subcommands to save catalog snapshots and to compare them with each other or the live catalog.
+mod snapshot {
+    use super::*;
+    use service_world::diff::{Diff, InstanceDiff};
+    use std::fmt::Display;
+
+    pub fn save(sub_args: &ArgMatches, settings: &Settings, consul: &Consul) -> Result<()> {
+        let file = sub_args.value_of("out").ok_or_else(|| {
+            ErrorKind::CliError("Snapshot file not specified".to_string())
+        })?;
+        let catalog = settings.catalog(consul)?;
+
+        let mut buffer = Vec::new();
+        serde_json::to_writer_pretty(&mut buffer, &catalog).chain_err(|| ErrorKind::OutputError)?;
+        write_atomically(Path::new(file), &buffer)
+    }
+
+    /// Compares the snapshot `from` with the snapshot `to` or the live catalog if `to` is missing.
+    pub fn diff(args: &ArgMatches, sub_args: &ArgMatches, settings: &Settings, consul: Option<&Consul>) -> Result<()> {
+        let from = sub_args.value_of("from").ok_or_else(|| {
+            ErrorKind::CliError("Snapshot file not specified".to_string())
+        })?;
+        let old = load(from)?;
+        let new = match (sub_args.value_of("to"), consul) {
+            (Some(to), _) => load(to)?,
+            (None, Some(consul)) => settings.catalog(consul)?,
+            (None, None) => bail!(ErrorKind::CliError("Url not specified".to_string())),
+        };
+        let diff = Diff::between(&old, &new);
+
+        let stdout = io::stdout();
+        let mut w = stdout.lock();
+        if settings.output == "json" {
+            serde_json::to_writer_pretty(&mut w, &diff).chain_err(|| ErrorKind::OutputError)?;
+            writeln!(w).chain_err(|| ErrorKind::OutputError)
+        } else {
+            terminal_output(&mut w, &diff, Colors::from_args(args)).chain_err(|| ErrorKind::OutputError)
+        }
+    }
+
+    fn load(file: &str) -> Result<Catalog> {
+        let reader = File::open(file).chain_err(|| {
+            ErrorKind::CliError(format!("Could not open snapshot '{}'", file))
+        })?;
+        serde_json::from_reader(reader).chain_err(|| {
+            ErrorKind::CliError(format!("Could not read snapshot '{}'", file))
+        })
+    }
+
+    fn terminal_output(w: &mut dyn Write, diff: &Diff, colors: Colors) -> io::Result<()> {
+        if diff.is_empty() {
+            return writeln!(w, "No changes");
+        }
+
+        let added = |what: &str, x: &dyn Display| colors.style(Color::Green).paint(format!("+ {} {}", what, x));
+        let removed = |what: &str, x: &dyn Display| colors.style(Color::Red).paint(format!("- {} {}", what, x));
+        let changed = |what: &str, x: &dyn Display| colors.style(Color::Yellow).paint(format!("~ {} {}", what, x));
+
+        for x in &diff.added_services {
+            writeln!(w, "{}", added("service", x))?;
+        }
+        for x in &diff.removed_services {
+            writeln!(w, "{}", removed("service", x))?;
+        }
+        for x in &diff.added_nodes {
+            writeln!(w, "{}", added("node", x))?;
+        }
+        for x in &diff.removed_nodes {
+            writeln!(w, "{}", removed("node", x))?;
+        }
+        for service in &diff.changed_services {
+            writeln!(w, "{}", changed("service", &service.name))?;
+            for x in &service.added_tags {
+                writeln!(w, "    {}", added("tag", x))?;
+            }
+            for x in &service.removed_tags {
+                writeln!(w, "    {}", removed("tag", x))?;
+            }
+            for x in &service.added_instances {
+                writeln!(w, "    {}", added("instance", &format!("{}/{}", x.node, x.service_id)))?;
+            }
+            for x in &service.removed_instances {
+                writeln!(w, "    {}", removed("instance", &format!("{}/{}", x.node, x.service_id)))?;
+            }
+            for x in &service.changed_instances {
+                writeln!(w, "    {}", changed("instance", &format!("{}/{}", x.node, x.service_id)))?;
+                instance_output(w, x, colors)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instance_output(w: &mut dyn Write, instance: &InstanceDiff, colors: Colors) -> io::Result<()> {
+        if let Some(ref address) = instance.address {
+            writeln!(w, "        address {} -> {}", address.from, address.to)?;
+        }
+        if let Some(ref port) = instance.port {
+            writeln!(w, "        port {} -> {}", port.from, port.to)?;
+        }
+        for x in &instance.added_tags {
+            writeln!(w, "        {}", colors.style(Color::Green).paint(format!("+ tag {}", x)))?;
+        }
+        for x in &instance.removed_tags {
+            writeln!(w, "        {}", colors.style(Color::Red).paint(format!("- tag {}", x)))?;
+        }
+        if let Some(ref healthy) = instance.healthy {
+            let health = |healthy: bool| {
+                if healthy {
+                    colors.style(Color::Green).paint("up")
+                } else {
+                    colors.style(Color::Red).paint("DOWN")
+                }
+            };
+            writeln!(w, "        health {} -> {}", health(healthy.from), health(healthy.to))?;
+        }
+
+        Ok(())
+    }
+}
+
+mod tui {
+    use super::*;
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::text::{Line, Span};
+    use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+    use ratatui::{DefaultTerminal, Frame};
+    use service_world::consul::Node;
+    use service_world::present;
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+    use std::time::Instant;
+
+    #[derive(PartialEq)]
+    enum Focus {
+        Services,
+        Instances,
+    }
+
+    struct App {
+        catalog: Option<Catalog>,
+        config: Config,
+        last_update: Option<Instant>,
+        status: Option<String>,
+        query: String,
+        searching: bool,
+        focus: Focus,
+        services: ListState,
+        instances: ListState,
+    }
+
+    /// Runs the interactive dashboard; the catalog is fetched in the background every interval
+    /// or when `r` is pressed.
+    pub fn run(settings: Settings, consul: Consul, interval: Duration) -> Result<()> {
+        let Settings {
+            config,
+            services,
+            tags,
+            ..
+        } = settings;
+        let (catalog_tx, catalog_rx) = mpsc::channel();
+        let (refresh_tx, refresh_rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let catalog = consul
+                .catalog_by(services.clone(), tags.clone())
+                .map_err(|e| e.to_string());
+            if catalog_tx.send(catalog).is_err() {
+                break;
+            }
+            match refresh_rx.recv_timeout(interval) {
+                Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        });
+
+        let mut app = App {
+            catalog: None,
+            config,
+            last_update: None,
+            status: Some("Loading catalog ...".to_string()),
+            query: String::new(),
+            searching: false,
+            focus: Focus::Services,
+            services: ListState::default().with_selected(Some(0)),
+            instances: ListState::default(),
+        };
+
+        let mut terminal = ratatui::init();
+        let result = event_loop(&mut terminal, &mut app, &catalog_rx, &|| {
+            let _ = refresh_tx.send(());
+        });
+        ratatui::restore();
+
+        result
+    }
+
+    fn event_loop(
+        terminal: &mut DefaultTerminal,
+        app: &mut App,
+        catalog_rx: &Receiver<::std::result::Result<Catalog, String>>,
+        refresh: &dyn Fn(),
+    ) -> Result<()> {
+        loop {
+            while let Ok(catalog) = catalog_rx.try_recv() {
+                match catalog {
+                    Ok(catalog) => {
+                        app.catalog = Some(catalog);
+                        app.last_update = Some(Instant::now());
+                        app.status = None;
+                        // Rendering an empty list resets the selection
+                        if app.services.selected().is_none() {
+                            app.services.select(Some(0));
+                        }
+                    }
+                    Err(e) => app.status = Some(format!("Failed to update catalog: {}", e)),
+                }
+            }
+
+            terminal
+                .draw(|frame| draw(frame, app))
+                .chain_err(|| ErrorKind::OutputError)?;
+
+            if !event::poll(Duration::from_millis(250)).chain_err(|| ErrorKind::OutputError)? {
+                continue;
+            }
+            let key = match event::read().chain_err(|| ErrorKind::OutputError)? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            if app.searching {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Enter => app.searching = false,
+                    KeyCode::Backspace => {
+                        app.query.pop();
+                        app.services.select(Some(0));
+                    }
+                    KeyCode::Char(c) => {
+                        app.query.push(c);
+                        app.services.select(Some(0));
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('/') => app.searching = true,
+                KeyCode::Esc => {
+                    app.query.clear();
+                    app.services.select(Some(0));
+                }
+                KeyCode::Char('r') => refresh(),
+                KeyCode::Tab | KeyCode::Left | KeyCode::Right => {
+                    app.focus = if app.focus == Focus::Services {
+                        app.instances.select(Some(0));
+                        Focus::Instances
+                    } else {
+                        Focus::Services
+                    };
+                }
+                KeyCode::Down | KeyCode::Char('j') => app.selected_list().select_next(),
+                KeyCode::Up | KeyCode::Char('k') => app.selected_list().select_previous(),
+                KeyCode::Enter | KeyCode::Char('o') => app.open_default_url(),
+                _ => {}
+            }
+        }
+    }
+
+    impl App {
+        fn selected_list(&mut self) -> &mut ListState {
+            match self.focus {
+                Focus::Services => &mut self.services,
+                Focus::Instances => &mut self.instances,
+            }
+        }
+
+        fn filtered_services(&self) -> Vec<&String> {
+            let catalog = match self.catalog {
+                Some(ref catalog) => catalog,
+                None => return Vec::new(),
+            };
+            let mut services: Vec<_> = catalog
+                .services()
+                .into_iter()
+                .filter(|name| {
+                    fuzzy_match(&self.query, name)
+                        || catalog
+                            .service_tags(name)
+                            .unwrap_or_default()
+                            .iter()
+                            .any(|tag| fuzzy_match(&self.query, tag))
+                })
+                .collect();
+            services.sort();
+
+            services
+        }
+
+        fn selected_service(&self) -> Option<&String> {
+            let services = self.filtered_services();
+            self.services
+                .selected()
+                .map(|i| i.min(services.len().saturating_sub(1)))
+                .and_then(|i| services.get(i).cloned())
+        }
+
+        fn instances(&self, service_name: &str) -> Vec<&Node> {
+            let mut nodes = self
+                .catalog
+                .as_ref()
+                .and_then(|catalog| catalog.nodes_by_service(service_name))
+                .unwrap_or_default();
+            nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+            nodes
+        }
+
+        fn open_default_url(&mut self) {
+            let service_name = match self.selected_service() {
+                Some(service_name) => service_name.clone(),
+                None => return,
+            };
+            let instances = self.instances(&service_name);
+            let node = match self.instances.selected().and_then(|i| instances.get(i)) {
+                Some(node) if self.focus == Focus::Instances => node,
+                _ => {
+                    self.status = Some("Select an instance to open its default link".to_string());
+                    return;
+                }
+            };
+            let url = present::generate_service_urls(&self.config, &service_name, node)
+                .ok()
+                .and_then(|mut urls| urls.remove("default"));
+
+            self.status = Some(match url {
+                Some(url) => match open_url(&url) {
+                    Ok(()) => format!("Opened {}", url),
+                    Err(e) => format!("Failed to open {}: {}", url, e),
+                },
+                None => format!("No default link configured for service '{}'", service_name),
+            });
+        }
+    }
+
+    fn draw(frame: &mut Frame, app: &mut App) {
+        let [main, status_bar] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+        let border = |focused: bool| {
+            if focused {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            }
+        };
+
+        let services = app.filtered_services();
+        let service_items: Vec<_> = services
+            .iter()
+            .map(|name| {
+                let nodes = app.instances(name);
+                let unhealthy = app.catalog.as_ref().map_or(0, |catalog| {
+                    nodes
+                        .iter()
+                        .filter(|node| !catalog.is_node_healthy_for_service(node, name))
+                        .count()
+                });
+                let color = if unhealthy == 0 { Color::Green } else { Color::Red };
+                ListItem::new(Line::from(vec![
+                    Span::styled(name.to_string(), Style::default().fg(Color::Yellow)),
+                    Span::styled(format!(" ({}/{})", nodes.len() - unhealthy, nodes.len()), Style::default().fg(color)),
+                ]))
+            })
+            .collect();
+        let service_list = List::new(service_items)
+            .block(Block::bordered().title(" Services ").border_style(border(app.focus == Focus::Services)))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(service_list, left, &mut app.services);
+
+        let instance_items: Vec<_> = match (app.selected_service(), app.catalog.as_ref()) {
+            (Some(service_name), Some(catalog)) => app
+                .instances(service_name)
+                .into_iter()
+                .map(|node| {
+                    let (color, health_indicator) = if catalog.is_node_healthy_for_service(node, service_name) {
+                        (Color::Green, "up  ")
+                    } else {
+                        (Color::Red, "DOWN")
+                    };
+                    ListItem::new(Line::from(vec![
+                        Span::styled(format!("{} {}", health_indicator, node.name), Style::default().fg(color)),
+                        Span::raw(format!("  {}:{}  ", node.address, node.service_port)),
+                        Span::styled(format!("{:?}", node.service_tags), Style::default().fg(Color::Blue)),
+                    ]))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let title = format!(" {} ", app.selected_service().map_or("Instances", |x| x.as_str()));
+        let instance_list = List::new(instance_items)
+            .block(Block::bordered().title(title).border_style(border(app.focus == Focus::Instances)))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(instance_list, right, &mut app.instances);
+
+        let status = if app.searching {
+            Line::from(format!("/{}", app.query))
+        } else if let Some(ref status) = app.status {
+            Line::from(status.to_string())
+        } else {
+            let age = app.last_update.map_or(0, |x| x.elapsed().as_secs());
+            let filter = if app.query.is_empty() {
+                String::new()
+            } else {
+                format!("filter '{}'  ", app.query)
+            };
+            Line::from(format!(
+                "{}updated {}s ago  q quit  / search  tab switch  o open link  r refresh",
+                filter, age
+            ))
+        };
+        frame.render_widget(Paragraph::new(status), status_bar);
+    }
+
+    /// Matches if all characters of `pattern` occur in `text` in the same order, ignoring case.
+    fn fuzzy_match(pattern: &str, text: &str) -> bool {
+        let mut chars = text.chars().flat_map(char::to_lowercase);
+        pattern
+            .chars()
+            .flat_map(char::to_lowercase)
+            .all(|p| chars.any(|c| c == p))
+    }
+
+    fn open_url(url: &str) -> Result<()> {
+        let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+        Command::new(opener)
+            .arg(url)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .chain_err(|| ErrorKind::CommandError(opener.to_string()))?;
+
+        Ok(())
+    }
 }
 
 error_chain! {
@@ -214,10 +1238,19 @@ error_chain! {
             description("Output failed")
             display("Output failed")
         }
+
+        CommandError(command: String) {
+            description("Command failed")
+            display("Command '{}' failed", command)
+        }
     }
 
-    links {
-        Consul(service_world::consul::Error, service_world::consul::ErrorKind);
+    foreign_links {
+        Config(service_world::config::Error);
+        Consul(service_world::consul::Error);
+        Discover(service_world::discover::Error);
+        Logging(service_world::logging::Error);
+        Present(service_world::present::Error);
     }
 }
 
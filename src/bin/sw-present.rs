@@ -7,24 +7,42 @@
 extern crate error_chain;
 extern crate clap;
 #[macro_use]
+extern crate log;
+#[macro_use]
 extern crate rocket;
 extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
 extern crate service_world;
+extern crate signal_hook;
+extern crate time;
+extern crate toml;
 
-use clap::{App, Arg};
-use service_world::config::Config;
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+use error_chain::ChainedError;
+use service_world::config::{self, Config, ConfigFile, SharedConfig};
 use service_world::consul::Consul;
+use service_world::logging;
 use service_world::present;
-use std::path::Path;
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::thread;
+use std::time::Duration;
 
 fn run() -> Result<()> {
     let args = build_cli().get_matches();
 
-    let config = if let Some(config_file) = args.value_of("config") {
-        Config::from_file(Path::new(config_file))
-    } else {
-        Ok(Default::default())
-    }.unwrap(); // Safe
+    if let Some(shell) = args.value_of("completions") {
+        return generate_completions(shell);
+    }
+    init_logging(&args)?;
+
+    let config_file = config_file(&args)?;
+    let config = Config::load(config_file.as_ref())?;
+
+    if let ("config", Some(sub_args)) = args.subcommand() {
+        return config_command::run(sub_args, &config, config_file.as_ref());
+    }
 
     // TODO: Consul Client should take all URLs and decides which to use by itself.
     let url: String = {
@@ -32,7 +50,7 @@ fn run() -> Result<()> {
             .or_else(||
                 // This is Rust at its not so finest: There's no coercing from Option<&String>
                 // to Option<&str>, so we have to reborrow.
-                config.consul.urls.get(0).map(|x| &**x))
+                config.consul.urls.first().map(|x| &**x))
             .ok_or_else(|| {
                 ErrorKind::CliError(
                     "Url is neither specified as CLI parameter nor in configuration file"
@@ -41,15 +59,94 @@ fn run() -> Result<()> {
             })?;
         s.to_string()
     };
-    let consul = Consul::new(url);
+    let consul = match config.consul.token {
+        Some(ref token) => Consul::with_token(url, token.clone()),
+        None => Consul::new(url),
+    };
 
     if args.is_present("rocket") {
+        let config = SharedConfig::new(config);
+        let reload_interval = reload_interval(&args)?;
+        if reload_interval > 0 {
+            config.watch(
+                config_file.clone(),
+                Duration::from_secs(reload_interval),
+                log_reload(&config),
+            );
+        }
+        reload_on_hangup(&config, config_file, log_reload(&config))?;
         web::launch_rocket(config, consul)
     } else {
         stdout::gen_services_html(&config, &consul)
     }
 }
 
+fn init_logging(args: &ArgMatches) -> Result<()> {
+    let format = args.value_of("log format").unwrap_or("text").parse()?;
+    logging::init(args.occurrences_of("verbose"), format)?;
+
+    Ok(())
+}
+
+/// Returns the config file given on the command line with its format given explicitly or by its
+/// extension.
+fn config_file(args: &ArgMatches) -> Result<Option<ConfigFile>> {
+    let path = match args.value_of("config") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    match args.value_of("config format") {
+        Some(format) => Ok(Some(ConfigFile::with_format(path, format.parse()?))),
+        None => Ok(Some(ConfigFile::new(path))),
+    }
+}
+
+fn reload_interval(args: &ArgMatches) -> Result<u64> {
+    let value = args.value_of("reload interval").unwrap_or("5");
+    value.parse().chain_err(|| {
+        ErrorKind::CliError(format!("Invalid reload interval '{}'", value))
+    })
+}
+
+/// Returns a callback logging the outcome of a config reload.
+fn log_reload(config: &SharedConfig) -> impl Fn(config::Result<()>) + Send + 'static {
+    // The Consul client is shared by all requests and keeps its URL and token until restart
+    let shared = config.clone();
+    let consul_config = config.get().consul.clone();
+
+    move |result| match result {
+        Ok(()) => {
+            info!("Reloaded configuration");
+            if shared.get().consul != consul_config {
+                warn!("Changes of consul.urls and consul.token take effect only after a restart");
+            }
+        }
+        Err(e) => error!(
+            "Failed to reload configuration, keeping the current one: {}",
+            Error::from(e).display_chain()
+        ),
+    }
+}
+
+/// Reloads the config on SIGHUP, e.g., sent by `service sw-present reload`, independent of the
+/// reload interval.
+fn reload_on_hangup<F>(config: &SharedConfig, config_file: Option<ConfigFile>, on_reload: F) -> Result<()>
+where
+    F: Fn(config::Result<()>) + Send + 'static,
+{
+    let mut signals = Signals::new([SIGHUP]).chain_err(|| ErrorKind::SignalError)?;
+    let shared = config.clone();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("Reloading configuration on SIGHUP");
+            on_reload(shared.reload(config_file.as_ref()));
+        }
+    });
+
+    Ok(())
+}
+
 fn build_cli() -> App<'static, 'static> {
     let name = "sw-present";
     let version = env!("CARGO_PKG_VERSION");
@@ -66,10 +163,32 @@ fn build_cli() -> App<'static, 'static> {
             Arg::with_name("config")
                 .short("c")
                 .long("config")
-                .required(true)
                 .takes_value(true)
                 .conflicts_with("completions")
-                .help("Sets config file"),
+                .help("Sets config file overriding the system and user config files"),
+        )
+        .arg(
+            Arg::with_name("config format")
+                .value_name("format")
+                .long("config-format")
+                .takes_value(true)
+                .requires("config")
+                .possible_values(&["toml", "yaml", "json"])
+                .help("Sets format of config file instead of guessing it from the extension"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Increases log verbosity; -v logs requests, -vv also Consul requests"),
+        )
+        .arg(
+            Arg::with_name("log format")
+                .value_name("format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .help("Sets format of log messages on stderr [default: text]"),
         )
         .arg(
             Arg::with_name("rocket")
@@ -78,14 +197,48 @@ fn build_cli() -> App<'static, 'static> {
                 .conflicts_with("completions")
                 .help("Sets Rocket mode -- activates internal web server"),
         )
+        .arg(
+            Arg::with_name("reload interval")
+                .value_name("seconds")
+                .long("reload-interval")
+                .takes_value(true)
+                .default_value("5")
+                .conflicts_with("completions")
+                .help("Sets interval to check config files for changes in Rocket mode; 0 disables reloading"),
+        )
         .arg(
             Arg::with_name("completions")
                 .long("completions")
                 .takes_value(true)
                 .hidden(true)
-                .possible_values(&["bash", "fish", "zsh"])
+                .possible_values(&["bash", "fish", "zsh", "powershell", "elvish"])
                 .help("The shell to generate the script for"),
         )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Checks and shows the configuration")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("validate")
+                        .about("Validates the configuration and exits with 1 on any problem"),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the config files and environment variables in use")
+                        .arg(
+                            Arg::with_name("effective")
+                                .long("effective")
+                                .help("Shows the merged configuration instead"),
+                        ),
+                ),
+        )
+}
+
+fn generate_completions(shell: &str) -> Result<()> {
+    let shell = shell.parse::<Shell>().map_err(ErrorKind::CliError)?;
+    build_cli().gen_completions_to("sw-present", shell, &mut std::io::stdout());
+
+    Ok(())
 }
 
 mod stdout {
@@ -93,46 +246,492 @@ mod stdout {
 
     pub fn gen_services_html(config: &Config, consul: &Consul) -> Result<()> {
         let mut writer = std::io::stdout();
-        present::gen_services_html(config, consul, &mut writer).map_err(|e| e.into())
+        let visibility = present::Visibility::for_user(config, None);
+        present::gen_services_html(config, consul, &Default::default(), &visibility, &mut writer)
+            .map_err(|e| e.into())
+    }
+}
+
+mod config_command {
+    use super::*;
+    use service_world::config::{self, Format, ENV_OVERRIDES};
+    use service_world::validate;
+    use std::env;
+
+    pub fn run(sub_args: &ArgMatches, config: &Config, config_file: Option<&ConfigFile>) -> Result<()> {
+        match sub_args.subcommand() {
+            ("validate", Some(_)) => validate(config, config_file),
+            ("show", Some(show_args)) if show_args.is_present("effective") => show_effective(config),
+            ("show", Some(_)) => show_layers(config_file),
+            _ => bail!(ErrorKind::CliError("Config subcommand not specified".to_string())),
+        }
+    }
+
+    fn validate(config: &Config, config_file: Option<&ConfigFile>) -> Result<()> {
+        let problems = validate::validate(config, &Config::files(config_file));
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        if !problems.is_empty() {
+            bail!(ErrorKind::InvalidConfig(problems.len()));
+        }
+        println!("Configuration is valid");
+
+        Ok(())
+    }
+
+    fn show_effective(config: &Config) -> Result<()> {
+        // Serializing a `Value` orders plain values before tables as TOML requires
+        let mut value = toml::Value::try_from(config).chain_err(|| ErrorKind::OutputError)?;
+        config::redact(&mut value);
+        let content = toml::to_string_pretty(&value).chain_err(|| ErrorKind::OutputError)?;
+        print!("{}", content);
+
+        Ok(())
+    }
+
+    /// Prints each layer in the format of its file; comments are lost because secrets are
+    /// redacted.
+    fn show_layers(config_file: Option<&ConfigFile>) -> Result<()> {
+        for file in Config::files(config_file) {
+            let mut value = Config::read_layer(&file)?;
+            config::redact(&mut value);
+            let content = match file.format {
+                Format::Toml => toml::to_string_pretty(&value).chain_err(|| ErrorKind::OutputError)?,
+                Format::Yaml => serde_yaml::to_string(&value).chain_err(|| ErrorKind::OutputError)?,
+                Format::Json => serde_json::to_string_pretty(&value).chain_err(|| ErrorKind::OutputError)?,
+            };
+            println!("# {} ({})", file, file.format);
+            println!("{}", content);
+        }
+        for &(section, key, _) in ENV_OVERRIDES {
+            let name = config::env_var_name(section, key);
+            if let Ok(value) = env::var(&name) {
+                if config::is_secret_env_var(section, key) {
+                    println!("# {}={}", name, config::REDACTED);
+                } else {
+                    println!("# {}={}", name, value);
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
 mod web {
-    use rocket::{Request, State};
-    use rocket::response::content;
+    use rocket::{Data, Outcome, Request, Response, State};
+    use rocket::fairing::{Fairing, Info, Kind};
+    use rocket::http::{ContentType, Cookie, Cookies, SameSite, Status};
+    use rocket::request::{self, FromRequest};
+    use rocket::response::{self, content, status, Redirect, Responder};
+    use service_world::auth::{self, OidcProvider};
+    use service_world::config::AuthMethod;
+    use service_world::metrics;
+    use service_world::readiness;
+    use std::io::Cursor;
+    use std::time::Instant;
     use super::*;
 
+    const USER_COOKIE: &str = "sw_user";
+    const STATE_COOKIE: &str = "sw_oidc_state";
+    /// Time users have to log in at the OpenID Connect provider.
+    const STATE_COOKIE_MINUTES: i64 = 10;
+    /// Time Consul has to answer for sw-present to be ready.
+    const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// The user a request is authenticated as according to `auth.method`; `None` if
+    /// authentication is disabled.
+    pub struct User(Option<String>);
+
+    impl User {
+        pub fn name(&self) -> Option<&str> {
+            self.0.as_deref()
+        }
+    }
+
+    impl<'a, 'r> FromRequest<'a, 'r> for User {
+        type Error = ();
+
+        fn from_request(request: &'a Request<'r>) -> request::Outcome<User, ()> {
+            let config = match request.guard::<State<SharedConfig>>() {
+                Outcome::Success(config) => config.get(),
+                _ => return Outcome::Failure((Status::InternalServerError, ())),
+            };
+            let settings = &config.auth;
+
+            let user = match settings.method {
+                AuthMethod::None => return Outcome::Success(User(None)),
+                AuthMethod::Basic => request
+                    .headers()
+                    .get_one("Authorization")
+                    .and_then(|header| auth::verify_basic(&settings.basic, header)),
+                AuthMethod::Proxy => {
+                    // Only the peer address counts; forwarding headers are up to the client.
+                    let trusted = request
+                        .remote()
+                        .map(|remote| auth::is_trusted_proxy(&settings.proxy, remote.ip()).unwrap_or(false))
+                        .unwrap_or(false);
+                    if !trusted {
+                        return Outcome::Failure((Status::Forbidden, ()));
+                    }
+                    request.headers().get_one(&settings.proxy.header).map(|x| x.to_string())
+                }
+                AuthMethod::Oidc => request
+                    .cookies()
+                    .get_private(USER_COOKIE)
+                    .map(|cookie| cookie.value().to_string()),
+            };
+
+            match user {
+                Some(name) => Outcome::Success(User(Some(name))),
+                None => Outcome::Failure((Status::Unauthorized, ())),
+            }
+        }
+    }
+
     #[get("/")]
-    #[allow(needless_pass_by_value)]
-    fn index(config: State<Config>) -> Result<content::Html<String>> {
+    #[allow(clippy::needless_pass_by_value)]
+    fn index(user: User, config: State<SharedConfig>) -> Result<content::Html<String>> {
+        let config = config.get();
+        let visibility = present::Visibility::for_user(&config, user.name());
         let mut buffer = vec![];
-        present::gen_index_html(&config, &mut buffer)?;
+        present::gen_index_html(&config, &visibility, &mut buffer)?;
 
         String::from_utf8(buffer).map(content::Html).map_err(|_| {
             Error::from(ErrorKind::OutputError)
         })
     }
 
-    #[get("/services")]
-    #[allow(needless_pass_by_value)]
-    fn services(config: State<Config>, consul: State<Consul>) -> Result<content::Html<String>> {
+    #[get("/services/<name>")]
+    #[allow(clippy::needless_pass_by_value)]
+    fn service(
+        user: User,
+        config: State<SharedConfig>,
+        consul: State<Consul>,
+        name: String,
+    ) -> Result<content::Html<String>> {
         let mut buffer = vec![];
-        present::gen_services_html(&config, &consul, &mut buffer)?;
+        let config = config.get();
+        let visibility = present::Visibility::for_user(&config, user.name());
+        present::gen_service_html(&config, &consul, &name, &visibility, &mut buffer)?;
 
         String::from_utf8(buffer).map(content::Html).map_err(|_| {
             Error::from(ErrorKind::OutputError)
         })
     }
 
+    #[get("/services?<q>&<tag>&<health>&<sort>")]
+    #[allow(clippy::needless_pass_by_value)]
+    fn services(
+        user: User,
+        config: State<SharedConfig>,
+        consul: State<Consul>,
+        q: Option<String>,
+        tag: Option<String>,
+        health: Option<String>,
+        sort: Option<String>,
+    ) -> Result<content::Html<String>> {
+        let query = present::Query::from_params(q, tag, health, sort)?;
+        let mut buffer = vec![];
+        let config = config.get();
+        let visibility = present::Visibility::for_user(&config, user.name());
+        present::gen_services_html(&config, &consul, &query, &visibility, &mut buffer)?;
+
+        String::from_utf8(buffer).map(content::Html).map_err(|_| {
+            Error::from(ErrorKind::OutputError)
+        })
+    }
+
+    /// Answers as long as the process is up; not subject to `[auth]` like `/readyz`.
+    #[get("/healthz")]
+    fn healthz() -> content::Json<&'static str> {
+        content::Json(r#"{"status":"ok"}"#)
+    }
+
+    /// Answers 200 if Consul is reachable and the templates compile and 503 otherwise. If `[auth]`
+    /// is enabled, only authenticated users see the details of the checks; they are logged for
+    /// everyone else.
+    #[get("/readyz")]
+    #[allow(clippy::needless_pass_by_value)]
+    fn readyz(
+        user: Option<User>,
+        config: State<SharedConfig>,
+        consul: State<Consul>,
+    ) -> Result<status::Custom<content::Json<String>>> {
+        let config = config.get();
+        let readiness = readiness::check(&config, &consul, READY_TIMEOUT);
+        let status = if readiness.ready {
+            Status::Ok
+        } else {
+            Status::ServiceUnavailable
+        };
+        for check in readiness.checks.iter().filter(|x| !x.ok) {
+            warn!("Readiness check {} failed: {}", check.name, check.message.as_deref().unwrap_or_default());
+        }
+        let anonymous = user.as_ref().and_then(|x| x.name()).is_none();
+        let readiness = if anonymous && config.auth.method != AuthMethod::None {
+            readiness.without_messages()
+        } else {
+            readiness
+        };
+        let body = serde_json::to_string(&readiness).chain_err(|| ErrorKind::OutputError)?;
+
+        Ok(status::Custom(status, content::Json(body)))
+    }
+
+    /// Exposes sw-present's own metrics in the Prometheus text format; not subject to `[auth]` so
+    /// that Prometheus can scrape it.
+    #[get("/metrics")]
+    fn export_metrics() -> Result<content::Plain<String>> {
+        let mut buffer = vec![];
+        metrics::write(&mut buffer).chain_err(|| ErrorKind::OutputError)?;
+
+        String::from_utf8(buffer).map(content::Plain).map_err(|_| {
+            Error::from(ErrorKind::OutputError)
+        })
+    }
+
+    /// Records count and latency of requests by route.
+    struct RequestMetrics;
+
+    impl Fairing for RequestMetrics {
+        fn info(&self) -> Info {
+            Info {
+                name: "Request metrics",
+                kind: Kind::Request | Kind::Response,
+            }
+        }
+
+        fn on_request(&self, request: &mut Request, _: &Data) {
+            request.local_cache(Instant::now);
+        }
+
+        fn on_response(&self, request: &Request, response: &mut Response) {
+            let start = request.local_cache(Instant::now);
+            let route = request.route().map(|route| route.uri.path()).unwrap_or("unmatched");
+            metrics::observe_request(route, response.status().code, start.elapsed());
+        }
+    }
+
+    struct RequestId(String);
+
+    /// Logs requests and tags all messages logged while handling a request, e.g., Consul requests,
+    /// with the request's `X-Request-Id` or a new id, which the response returns.
+    struct RequestLog;
+
+    impl Fairing for RequestLog {
+        fn info(&self) -> Info {
+            Info {
+                name: "Request log",
+                kind: Kind::Request | Kind::Response,
+            }
+        }
+
+        fn on_request(&self, request: &mut Request, _: &Data) {
+            let request_id = request
+                .headers()
+                .get_one("X-Request-Id")
+                .filter(|x| is_valid_request_id(x))
+                .map(|x| x.to_string())
+                .unwrap_or_else(logging::new_request_id);
+            logging::set_request_id(Some(request_id.clone()));
+            request.local_cache(|| RequestId(request_id));
+        }
+
+        fn on_response(&self, request: &Request, response: &mut Response) {
+            let start = request.local_cache(Instant::now);
+            info!(
+                "{} {} answered {} in {:?}",
+                request.method(),
+                request.uri(),
+                response.status(),
+                start.elapsed()
+            );
+            let RequestId(ref request_id) = *request.local_cache(|| RequestId(logging::new_request_id()));
+            response.set_raw_header("X-Request-Id", request_id.clone());
+            logging::set_request_id(None);
+        }
+    }
+
+    /// Accepts request ids of clients only if they cannot garble log lines.
+    fn is_valid_request_id(request_id: &str) -> bool {
+        !request_id.is_empty()
+            && request_id.len() <= 64
+            && request_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    #[get("/auth/login")]
+    #[allow(clippy::needless_pass_by_value)]
+    fn login(config: State<SharedConfig>, mut cookies: Cookies) -> Result<Redirect> {
+        let config = config.get();
+        let oidc = oidc_config(&config)?;
+        let provider = OidcProvider::discover(oidc)?;
+        let state = auth::random_state();
+        let url = provider.authorize_url(oidc, &state)?;
+        // The provider redirects back cross-site which drops cookies with SameSite=Strict
+        let lifetime = time::Duration::minutes(STATE_COOKIE_MINUTES);
+        let cookie = Cookie::build(STATE_COOKIE, state)
+            .same_site(SameSite::Lax)
+            .max_age(lifetime)
+            .expires(time::now() + lifetime)
+            .finish();
+        cookies.add_private(cookie);
+
+        Ok(Redirect::to(url))
+    }
+
+    #[get("/auth/callback?<code>&<state>")]
+    #[allow(clippy::needless_pass_by_value)]
+    fn callback(
+        config: State<SharedConfig>,
+        mut cookies: Cookies,
+        code: String,
+        state: String,
+    ) -> Result<Redirect> {
+        let expected_state = cookies.get_private(STATE_COOKIE);
+        cookies.remove_private(Cookie::named(STATE_COOKIE));
+        if expected_state.map(|x| x.value() != state).unwrap_or(true) {
+            bail!(ErrorKind::LoginFailed("state does not match login".to_string()));
+        }
+
+        let config = config.get();
+        let oidc = oidc_config(&config)?;
+        let user = OidcProvider::discover(oidc)?.user(oidc, &code)?;
+        cookies.add_private(Cookie::build(USER_COOKIE, user).same_site(SameSite::Lax).finish());
+
+        Ok(Redirect::to("/"))
+    }
+
+    #[get("/auth/logout")]
+    fn logout(mut cookies: Cookies) -> Redirect {
+        cookies.remove_private(Cookie::named(USER_COOKIE));
+
+        Redirect::to("/")
+    }
+
+    fn oidc_config(config: &Config) -> Result<&service_world::config::OidcAuth> {
+        config.auth.oidc.as_ref().ok_or_else(|| auth::Error::OidcNotConfigured.into())
+    }
+
+    /// Asks browsers for basic auth credentials or sends them to the OpenID Connect login,
+    /// respectively; other methods cannot ask for credentials.
+    #[catch(401)]
+    fn unauthorized(request: &Request) -> Response<'static> {
+        let config = match request.guard::<State<SharedConfig>>() {
+            Outcome::Success(config) => config.get(),
+            _ => return Response::build().status(Status::InternalServerError).finalize(),
+        };
+
+        match config.auth.method {
+            AuthMethod::Oidc => Response::build()
+                .status(Status::SeeOther)
+                .raw_header("Location", "/auth/login")
+                .finalize(),
+            AuthMethod::Basic => Response::build()
+                .status(Status::Unauthorized)
+                .raw_header("WWW-Authenticate", format!("Basic realm=\"{}\"", config.auth.basic.realm))
+                .sized_body(Cursor::new("unauthorized"))
+                .finalize(),
+            AuthMethod::None | AuthMethod::Proxy => {
+                error_page(request, Status::Unauthorized, "Authentication required", Vec::new())
+            }
+        }
+    }
+
+    #[catch(403)]
+    fn forbidden(request: &Request) -> Response<'static> {
+        error_page(request, Status::Forbidden, "Access denied", Vec::new())
+    }
+
     #[catch(404)]
-    fn not_found(_: &Request) -> String {
-        "not implemented".to_string()
+    fn not_found(request: &Request) -> Response<'static> {
+        let message = format!("There is no page at {}", request.uri().path());
+        error_page(request, Status::NotFound, &message, Vec::new())
+    }
+
+    #[catch(500)]
+    fn internal_error(request: &Request) -> Response<'static> {
+        error_page(request, Status::InternalServerError, "Internal error", Vec::new())
+    }
+
+    impl<'r> Responder<'r> for Error {
+        fn respond_to(self, request: &Request) -> response::Result<'r> {
+            let status = status_of(self.kind());
+            let chain: Vec<_> = self.iter().map(|x| x.to_string()).collect();
+            if status.code >= 500 {
+                error!("{} {} failed: {}", request.method(), request.uri(), chain.join(": "));
+            } else {
+                info!("{} {} failed: {}", request.method(), request.uri(), chain.join(": "));
+            }
+
+            Ok(error_page(request, status, &self.to_string(), chain))
+        }
     }
 
-    pub fn launch_rocket(config: Config, consul: Consul) -> Result<()> {
+    /// Maps errors to HTTP status codes, e.g., 502 if Consul is not available.
+    fn status_of(kind: &ErrorKind) -> Status {
+        match *kind {
+            ErrorKind::Auth(auth::Error::InvalidIpRange { .. }) | ErrorKind::Auth(auth::Error::OidcNotConfigured) => {
+                Status::InternalServerError
+            }
+            ErrorKind::Consul(_) | ErrorKind::Auth(_) => Status::BadGateway,
+            ErrorKind::Present(present::Error::Consul(_)) => Status::BadGateway,
+            ErrorKind::Present(present::Error::UnknownService { .. }) => Status::NotFound,
+            ErrorKind::Present(present::Error::InvalidQuery { .. }) | ErrorKind::LoginFailed(_) => {
+                Status::BadRequest
+            }
+            _ => Status::InternalServerError,
+        }
+    }
+
+    /// Renders the `error` template falling back to plain text, e.g., if it is not configured.
+    /// The error chain is shown only if `present.debug` is set.
+    fn error_page(request: &Request, status: Status, message: &str, chain: Vec<String>) -> Response<'static> {
+        let config = match request.guard::<State<SharedConfig>>() {
+            Outcome::Success(config) => Some(config.get()),
+            _ => None,
+        };
+        let debug = config.as_ref().map(|x| x.present.debug).unwrap_or(false);
+        let chain = if debug { chain } else { Vec::new() };
+
+        let html = config.and_then(|config| {
+            let page = present::ErrorPage {
+                project_name: &config.general.project_name,
+                status: status.code,
+                reason: status.reason,
+                message,
+                chain: chain.clone(),
+            };
+            let mut buffer = vec![];
+            present::gen_error_html(&config, &page, &mut buffer).ok()?;
+            String::from_utf8(buffer).ok()
+        });
+
+        let (content_type, body) = match html {
+            Some(html) => (ContentType::HTML, html),
+            None => {
+                let mut text = format!("{} {}: {}\n", status.code, status.reason, message);
+                for cause in &chain {
+                    text.push_str(&format!("caused by: {}\n", cause));
+                }
+                (ContentType::Plain, text)
+            }
+        };
+
+        Response::build()
+            .status(status)
+            .header(content_type)
+            .sized_body(Cursor::new(body))
+            .finalize()
+    }
+
+    pub fn launch_rocket(config: SharedConfig, consul: Consul) -> Result<()> {
         let rocket = rocket::ignite()
-            .register(catchers![not_found])
-            .mount("/", routes![index, services])
+            .register(catchers![unauthorized, forbidden, not_found, internal_error])
+            .attach(RequestMetrics)
+            .attach(RequestLog)
+            .mount("/", routes![index, service, services, healthz, readyz, export_metrics, login, callback, logout])
             .manage(config)
             .manage(consul);
 
@@ -159,12 +758,29 @@ error_chain! {
             description("Output failed")
             display("Output failed")
         }
+
+        LoginFailed(cause: String) {
+            description("Login failed")
+            display("Login failed because {}", cause)
+        }
+
+        InvalidConfig(problems: usize) {
+            description("Invalid configuration")
+            display("Invalid configuration; found {} problem(s)", problems)
+        }
+
+        SignalError {
+            description("Failed to handle signals")
+            display("Failed to handle signals")
+        }
     }
 
-    links {
-        Config(service_world::config::Error, service_world::config::ErrorKind);
-        Consul(service_world::consul::Error, service_world::consul::ErrorKind);
-        Present(service_world::present::Error, service_world::present::ErrorKind);
+    foreign_links {
+        Auth(service_world::auth::Error);
+        Config(service_world::config::Error);
+        Consul(service_world::consul::Error);
+        Logging(service_world::logging::Error);
+        Present(service_world::present::Error);
     }
 }
 
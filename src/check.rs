@@ -0,0 +1,213 @@
+use config::{Checks, MinPassing, NoCritical};
+use consul::Catalog;
+use std::fmt;
+
+/// Check states with their Nagios plugin exit codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Threshold of performance data in Nagios range syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Threshold {
+    /// Alerts if the value is above the limit; written as `10`.
+    AtMost(usize),
+    /// Alerts if the value is below the limit; written as `10:`.
+    AtLeast(usize),
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Threshold::AtMost(x) => write!(f, "{}", x),
+            Threshold::AtLeast(x) => write!(f, "{}:", x),
+        }
+    }
+}
+
+/// Performance data in Nagios plugin format `'label'=value;warn;crit;min;max`.
+#[derive(Debug)]
+pub struct PerfData {
+    pub label: String,
+    pub value: usize,
+    pub warning: Option<Threshold>,
+    pub critical: Option<Threshold>,
+}
+
+impl fmt::Display for PerfData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let threshold = |x: Option<Threshold>| x.map(|x| x.to_string()).unwrap_or_default();
+        write!(
+            f,
+            "'{}'={};{};{};0;",
+            self.label,
+            self.value,
+            threshold(self.warning),
+            threshold(self.critical)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub status: Status,
+    pub message: String,
+    pub perf_data: PerfData,
+}
+
+#[derive(Debug)]
+pub struct Summary {
+    pub status: Status,
+    pub results: Vec<CheckResult>,
+}
+
+impl Summary {
+    /// Evaluates all checks against the catalog; the worst result determines the overall status.
+    pub fn evaluate(catalog: &Catalog, checks: &Checks) -> Summary {
+        let results: Vec<_> = checks
+            .min_passing
+            .iter()
+            .map(|check| min_passing(catalog, check))
+            .chain(checks.no_critical.iter().map(|check| no_critical(catalog, check)))
+            .collect();
+        let status = if results.is_empty() {
+            Status::Unknown
+        } else {
+            results.iter().map(|x| x.status).max().unwrap_or(Status::Unknown)
+        };
+
+        Summary { status, results }
+    }
+}
+
+impl fmt::Display for Summary {
+    /// Prints a single line with the failed checks or all checks if everything is fine, followed
+    /// by the performance data of all checks.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.results.is_empty() {
+            return write!(f, "CHECK {} - no checks defined", self.status);
+        }
+
+        let messages: Vec<_> = self
+            .results
+            .iter()
+            .filter(|x| self.status == Status::Ok || x.status != Status::Ok)
+            .map(|x| x.message.as_str())
+            .collect();
+        let perf_data: Vec<_> = self.results.iter().map(|x| x.perf_data.to_string()).collect();
+
+        write!(
+            f,
+            "CHECK {} - {} | {}",
+            self.status,
+            messages.join(", "),
+            perf_data.join(" ")
+        )
+    }
+}
+
+fn min_passing(catalog: &Catalog, check: &MinPassing) -> CheckResult {
+    let passing = catalog
+        .nodes_by_service(&check.service)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|node| catalog.is_node_healthy_for_service(node, &check.service))
+        .count();
+
+    let status = if passing < check.critical {
+        Status::Critical
+    } else if check.warning.is_some_and(|warning| passing < warning) {
+        Status::Warning
+    } else {
+        Status::Ok
+    };
+
+    CheckResult {
+        status,
+        message: format!("{} has {} passing instances", check.service, passing),
+        perf_data: PerfData {
+            label: format!("{}_passing", check.service),
+            value: passing,
+            warning: check.warning.map(Threshold::AtLeast),
+            critical: Some(Threshold::AtLeast(check.critical)),
+        },
+    }
+}
+
+fn no_critical(catalog: &Catalog, check: &NoCritical) -> CheckResult {
+    let mut critical_services: Vec<_> = catalog
+        .services()
+        .into_iter()
+        .filter(|name| {
+            catalog
+                .service_tags(name)
+                .unwrap_or_default()
+                .contains(&&check.tag)
+        })
+        .filter_map(|name| {
+            let critical = catalog
+                .nodes_by_service(name)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|node| catalog.is_node_critical_for_service(node, name))
+                .count();
+            if critical > 0 {
+                Some((name, critical))
+            } else {
+                None
+            }
+        })
+        .collect();
+    critical_services.sort();
+
+    let critical: usize = critical_services.iter().map(|&(_, x)| x).sum();
+    let (status, message) = if critical_services.is_empty() {
+        (Status::Ok, format!("tag {} has no critical instances", check.tag))
+    } else {
+        let services: Vec<_> = critical_services
+            .iter()
+            .map(|&(name, count)| format!("{} ({})", name, count))
+            .collect();
+        (
+            Status::Critical,
+            format!(
+                "tag {} has critical instances in {}",
+                check.tag,
+                services.join(", ")
+            ),
+        )
+    };
+
+    CheckResult {
+        status,
+        message,
+        perf_data: PerfData {
+            label: format!("{}_critical", check.tag),
+            value: critical,
+            warning: None,
+            critical: Some(Threshold::AtMost(0)),
+        },
+    }
+}
@@ -1,86 +1,860 @@
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+use serde_json;
+use serde_path_to_error;
+use serde_yaml;
 use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use serde_ignored;
+use thiserror::Error;
 use toml;
+use toml::value::{Table, Value};
+use validate;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Config file of the system wide installation, e.g., by the Debian package.
+pub const SYSTEM_CONFIG_FILE: &str = "/etc/sw-present/sw-present.conf";
+
+/// Access group every user belongs to.
+pub const EVERYONE_GROUP: &str = "everyone";
+
+/// Replaces secrets in config output.
+pub const REDACTED: &str = "<redacted>";
+
+/// Paths of config values holding secrets; `*` matches any key or list entry.
+const SECRETS: &[&[&str]] = &[
+    &["consul", "token"],
+    &["profiles", "*", "consul", "token"],
+    &["auth", "basic", "users", "*", "password_hash"],
+    &["auth", "oidc", "client_secret"],
+];
+
+/// Config values that may be overridden by environment variables named `SW_<SECTION>_<KEY>`,
+/// e.g., `SW_CONSUL_URLS`. Lists are separated by `,`.
+pub const ENV_OVERRIDES: &[(&str, &str, EnvKind)] = &[
+    ("general", "project_name", EnvKind::String),
+    ("consul", "urls", EnvKind::List),
+    ("consul", "token", EnvKind::String),
+    ("present", "template_dir", EnvKind::String),
+    ("discover", "services", EnvKind::List),
+    ("discover", "tags", EnvKind::List),
+    ("discover", "output", EnvKind::String),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvKind {
+    String,
+    List,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub general: General,
     pub consul: Consul,
     pub present: Present,
+    pub discover: Discover,
     pub services: HashMap<String, Vec<Service>>,
+    pub service_info: HashMap<String, ServiceInfo>,
+    /// Explicit groups of service names used by `present.grouping.by = "groups"`.
+    pub groups: HashMap<String, Vec<String>>,
+    pub auth: Auth,
+    pub access: Access,
+    pub checks: Checks,
+    pub profiles: HashMap<String, Profile>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct General {
     pub project_name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Consul {
     pub urls: Vec<String>,
+    pub token: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Present {
     pub index_links: Vec<Service>,
     pub template_dir: String,
     pub templates: HashMap<String, String>,
+    pub grouping: Grouping,
+    /// Shows the error chain on error pages; may reveal internals like Consul URLs.
+    pub debug: bool,
+}
+
+/// Groups the services page; services may belong to multiple groups and those belonging to none
+/// are collected in a last group.
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Grouping {
+    pub by: GroupBy,
+    /// Groups by the remainder of tags starting with this prefix, e.g., `team:`; groups by whole
+    /// tags if unset.
+    pub tag_prefix: Option<String>,
+    /// Groups by the value of this service meta data key.
+    pub meta_key: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    /// Lists services alphabetically without groups.
+    #[default]
+    None,
+    TagPrefix,
+    Meta,
+    Datacenter,
+    Groups,
+}
+
+/// Authentication of sw-present's web server; `method` selects which of the sections applies.
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Auth {
+    pub method: AuthMethod,
+    pub basic: BasicAuth,
+    pub proxy: ProxyAuth,
+    pub oidc: Option<OidcAuth>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// Allows everyone.
+    #[default]
+    None,
+    Basic,
+    Proxy,
+    Oidc,
+}
+
+/// HTTP basic authentication against static users.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BasicAuth {
+    pub realm: String,
+    pub users: Vec<User>,
+}
+
+/// A user with a bcrypt password hash, e.g., created by `htpasswd -nB <name>`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct User {
+    pub name: String,
+    pub password_hash: String,
+}
+
+/// Trusts the user name a reverse proxy sets in `header` for requests from `trusted_proxies`,
+/// given as IP addresses or CIDR ranges.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProxyAuth {
+    pub header: String,
+    pub trusted_proxies: Vec<String>,
+}
+
+/// OpenID Connect authorization code flow against the provider at `issuer`; `redirect_url` must
+/// point to sw-present's `/auth/callback`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct OidcAuth {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// Claim of the user info to use as user name.
+    #[serde(default = "default_oidc_username_claim")]
+    pub username_claim: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Restricts the services users see on sw-present's pages; without `groups`, everyone sees all
+/// services.
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Access {
+    /// Groups of each user. Every user, even an anonymous one, also belongs to `EVERYONE_GROUP`.
+    pub users: HashMap<String, Vec<String>>,
+    pub groups: HashMap<String, AccessRule>,
+}
+
+/// Services the members of a group see: those matching any of the name patterns, in which `*`
+/// matches any text, or having any of the tags.
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AccessRule {
+    pub services: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// Defaults for sw-discover which apply unless overridden on the command line.
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Discover {
+    pub services: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+    pub output: Option<String>,
+}
+
+/// Replaces the `consul` and `discover` sections when selected, e.g., to switch clusters.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Profile {
+    pub consul: Option<Consul>,
+    pub discover: Option<Discover>,
+}
+
+/// A link of a start page or, in `[services]`, a link template rendered for each node of the
+/// service; `when`, `target`, and `description` only apply to the latter.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct Service {
     pub name: String,
     pub url: String,
     pub icon: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub target: Target,
+    #[serde(default)]
+    pub when: Condition,
+}
+
+/// Describes a service on the services page. Missing values fall back to the Consul service meta
+/// data keys of the same name, e.g., `team` or `runbook_url`.
+#[derive(Clone, Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServiceInfo {
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub team: Option<String>,
+    pub runbook_url: Option<String>,
+    pub docs_url: Option<String>,
+}
+
+/// Where to open a link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Same,
+    New,
+}
+
+/// Restricts a link template to nodes having all `tags` and all `meta` entries in their service
+/// meta data; an empty condition matches all nodes.
+#[derive(Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Condition {
+    pub tags: Vec<String>,
+    pub meta: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Checks {
+    pub min_passing: Vec<MinPassing>,
+    pub no_critical: Vec<NoCritical>,
+}
+
+/// Requires a service to have at least `critical` and, if set, `warning` passing instances.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct MinPassing {
+    pub service: String,
+    pub critical: usize,
+    pub warning: Option<usize>,
+}
+
+/// Requires that no instance of any service tagged with `tag` is critical; warnings are fine.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NoCritical {
+    pub tag: String,
+}
+
+impl Default for General {
+    fn default() -> General {
+        General { project_name: "Service World".to_string() }
+    }
 }
 
-impl Default for Config {
-    fn default() -> Config {
-        let general = General { project_name: "Service World".to_string() };
-        let consul = Consul { urls: vec!["http://localhost:8500".to_string()] };
-        let present = Present {
+impl Default for Consul {
+    fn default() -> Consul {
+        Consul {
+            urls: vec!["http://localhost:8500".to_string()],
+            token: None,
+        }
+    }
+}
+
+impl Default for BasicAuth {
+    fn default() -> BasicAuth {
+        BasicAuth {
+            realm: "sw-present".to_string(),
+            users: Vec::new(),
+        }
+    }
+}
+
+impl Default for ProxyAuth {
+    fn default() -> ProxyAuth {
+        ProxyAuth {
+            header: "X-Forwarded-User".to_string(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".to_string(), "profile".to_string(), "email".to_string()]
+}
+
+fn default_oidc_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+impl Default for Present {
+    fn default() -> Present {
+        Present {
             index_links: Vec::new(),
             template_dir: ".".to_string(),
             templates: HashMap::new(),
+            grouping: Grouping::default(),
+            debug: false,
+        }
+    }
+}
+
+/// Formats of config files; all of them share the same keys and semantics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Determines the format by the file extension `.yaml`, `.yml`, or `.json` and falls back to
+    /// TOML, e.g., for `sw-present.conf`.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("yaml") | Some("yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        match s {
+            "toml" => Ok(Format::Toml),
+            "yaml" => Ok(Format::Yaml),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::UnknownFormat { format: s.to_string() }),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Format::Toml => write!(f, "toml"),
+            Format::Yaml => write!(f, "yaml"),
+            Format::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// A config file together with its format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub format: Format,
+}
+
+impl ConfigFile {
+    /// Creates a config file whose format is determined by its extension.
+    pub fn new<P: Into<PathBuf>>(path: P) -> ConfigFile {
+        let path = path.into();
+        let format = Format::from_path(&path);
+
+        ConfigFile { path, format }
+    }
+
+    pub fn with_format<P: Into<PathBuf>>(path: P, format: Format) -> ConfigFile {
+        ConfigFile {
+            path: path.into(),
+            format,
+        }
+    }
+
+    fn read_to_string(&self) -> Result<String> {
+        let read = || -> io::Result<String> {
+            let mut file = File::open(&self.path)?;
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            Ok(content)
         };
-        let services = HashMap::new();
 
-        Config {
-            general,
-            consul,
-            present,
-            services,
+        read().map_err(|e| Error::Read {
+            file: self.path.clone(),
+            source: e,
+        })
+    }
+
+    /// Deserializes `content` according to the format; failures name the key path and, if
+    /// available, the line.
+    fn parse<T: DeserializeOwned>(&self, content: &str) -> Result<T> {
+        match self.format {
+            Format::Toml => self.deserialize(&mut toml::Deserializer::new(content)),
+            Format::Yaml => self.deserialize(serde_yaml::Deserializer::from_str(content)),
+            Format::Json => {
+                let mut deserializer = serde_json::Deserializer::from_str(content);
+                let value = self.deserialize(&mut deserializer)?;
+                deserializer.end().map_err(|e| Error::parse(&self.path, ".".to_string(), e))?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize<'de, D, T>(&self, deserializer: D) -> Result<T>
+    where
+        D: Deserializer<'de>,
+        D::Error: Into<ParseError>,
+        T: Deserialize<'de>,
+    {
+        serde_path_to_error::deserialize(deserializer)
+            .map_err(|e| Error::parse(&self.path, e.path().to_string(), e.into_inner()))
+    }
+}
+
+impl fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+/// Holds the current `Config` for concurrent readers and swaps it atomically on reload. Readers
+/// keep the config they got even if it is replaced in the meantime.
+#[derive(Clone, Debug)]
+pub struct SharedConfig {
+    current: Arc<RwLock<Arc<Config>>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> SharedConfig {
+        SharedConfig { current: Arc::new(RwLock::new(Arc::new(config))) }
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn replace(&self, config: Config) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+    }
+
+    /// Loads the layered config again and replaces the current one if its templates compile, just
+    /// as readiness requires. An invalid config is not applied so that the current one stays in
+    /// use.
+    pub fn reload(&self, config_file: Option<&ConfigFile>) -> Result<()> {
+        let config = Config::load(config_file)?;
+        let problems = validate::validate_template_files(&config);
+        if !problems.is_empty() {
+            return Err(Error::Invalid {
+                problems: problems.iter().map(|x| x.to_string()).collect(),
+            });
+        }
+        self.replace(config);
+
+        Ok(())
+    }
+
+    /// Polls the config files every interval and reloads the config if any of them has been
+    /// created, changed, or removed; `on_reload` is called with the outcome of each reload.
+    pub fn watch<F>(&self, config_file: Option<ConfigFile>, interval: Duration, on_reload: F) -> JoinHandle<()>
+    where
+        F: Fn(Result<()>) + Send + 'static,
+    {
+        let shared = self.clone();
+        thread::spawn(move || {
+            let mut last_modified = modification_times(config_file.as_ref());
+            loop {
+                thread::sleep(interval);
+                let modified = modification_times(config_file.as_ref());
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                on_reload(shared.reload(config_file.as_ref()));
+            }
+        })
+    }
+}
+
+fn modification_times(config_file: Option<&ConfigFile>) -> Vec<(PathBuf, Option<SystemTime>)> {
+    Some(PathBuf::from(SYSTEM_CONFIG_FILE))
+        .into_iter()
+        .chain(user_config_file())
+        .chain(config_file.map(|x| x.path.clone()))
+        .map(|file| {
+            let modified = file.metadata().and_then(|x| x.modified()).ok();
+            (file, modified)
+        })
+        .collect()
+}
+
+/// Returns whether an optional config file exists and may be read. Unreadable files are skipped
+/// with a warning, e.g., the system config file for users outside of the `sw-present` group.
+fn is_readable(file: &Path) -> bool {
+    if !file.is_file() {
+        return false;
+    }
+    match File::open(file) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Skipping config file {} which is not readable: {}", file.display(), e);
+            false
         }
     }
 }
 
+/// Returns `$XDG_CONFIG_HOME/service-world/config.toml` falling back to
+/// `~/.config/service-world/config.toml`.
+pub fn user_config_file() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("service-world").join("config.toml"))
+}
+
+/// Returns the name of the environment variable overriding `key` in `section`.
+pub fn env_var_name(section: &str, key: &str) -> String {
+    format!("SW_{}_{}", section, key).to_uppercase()
+}
+
 impl Config {
+    /// Loads the layered configuration: built-in defaults are overridden by the system config
+    /// file, the user config file, `config_file`, and finally `SW_*` environment variables.
+    ///
+    /// Missing or unreadable system and user config files are skipped while `config_file` must
+    /// exist. Each file is validated on its own so that errors name the file as well as line and
+    /// key.
+    pub fn load(config_file: Option<&ConfigFile>) -> Result<Config> {
+        Config::load_with_profile(config_file, None)
+    }
+
+    /// Like `load`, but the `consul` and `discover` sections of the named profile replace those
+    /// of the config files. Environment variables still override the profile.
+    pub fn load_with_profile(config_file: Option<&ConfigFile>, profile: Option<&str>) -> Result<Config> {
+        let mut merged = Value::Table(Table::new());
+        for file in &Config::files(config_file) {
+            info!("Loading config file {}", file);
+            let layer = Config::read_layer(file)?;
+            merge(&mut merged, layer);
+        }
+        if let Some(name) = profile {
+            apply_profile(&mut merged, name)?;
+        }
+        merge(&mut merged, env_layer());
+
+        serde_path_to_error::deserialize(merged).map_err(|e| Error::Merge {
+            path: e.path().to_string(),
+            source: e.into_inner(),
+        })
+    }
+
+    /// Returns the config files `load` reads in the order of their precedence.
+    pub fn files(config_file: Option<&ConfigFile>) -> Vec<ConfigFile> {
+        let mut files: Vec<_> = Some(PathBuf::from(SYSTEM_CONFIG_FILE))
+            .into_iter()
+            .chain(user_config_file())
+            .filter(|x| is_readable(x))
+            .map(ConfigFile::new)
+            .collect();
+        files.extend(config_file.cloned());
+
+        files
+    }
+
+    /// Returns the keys of a config file that do not belong to `Config` and are thus ignored,
+    /// e.g., because of a typo.
+    pub fn unknown_keys(config_file: &ConfigFile) -> Result<Vec<String>> {
+        let content = config_file.read_to_string()?;
+
+        let mut keys = Vec::new();
+        {
+            let mut collect = |path: serde_ignored::Path| keys.push(path.to_string());
+            let _: Config = match config_file.format {
+                Format::Toml => config_file.deserialize(serde_ignored::Deserializer::new(
+                    &mut toml::Deserializer::new(&content),
+                    &mut collect,
+                ))?,
+                Format::Yaml => config_file.deserialize(serde_ignored::Deserializer::new(
+                    serde_yaml::Deserializer::from_str(&content),
+                    &mut collect,
+                ))?,
+                Format::Json => config_file.deserialize(serde_ignored::Deserializer::new(
+                    &mut serde_json::Deserializer::from_str(&content),
+                    &mut collect,
+                ))?,
+            };
+        }
+
+        Ok(keys)
+    }
+
+    /// Reads a single config file whose format is determined by its extension.
     pub fn from_file(file_path: &Path) -> Result<Config> {
-        let mut file = File::open(file_path)?;
-        let content = Config::read_to_string(&mut file)?;
+        Config::from_file_as(file_path, Format::from_path(file_path))
+    }
+
+    pub fn from_file_as(file_path: &Path, format: Format) -> Result<Config> {
+        let config_file = ConfigFile::with_format(file_path, format);
+        let content = config_file.read_to_string()?;
 
-        Config::parse_toml(&content)
+        config_file.parse(&content)
     }
 
-    fn read_to_string(file: &mut File) -> Result<String> {
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
+    /// Reads and validates a single config file as it is merged into the layered config.
+    pub fn read_layer(config_file: &ConfigFile) -> Result<Value> {
+        let content = config_file.read_to_string()?;
+        // Deserialize into `Config` first because `Value` only reports syntax errors
+        let _: Config = config_file.parse(&content)?;
 
-        Ok(content)
+        match config_file.format {
+            Format::Toml => config_file.parse(&content),
+            Format::Yaml | Format::Json => config_file.parse(&content).map(json_to_toml),
+        }
     }
+}
+
+/// Converts a YAML or JSON document into TOML's data model to merge it with other layers. TOML
+/// has no null, so `null` values are dropped and treated as missing just like absent keys.
+fn json_to_toml(value: serde_json::Value) -> Value {
+    fn convert(value: serde_json::Value) -> Option<Value> {
+        match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::Bool(x) => Some(Value::Boolean(x)),
+            serde_json::Value::Number(x) => x
+                .as_i64()
+                .map(Value::Integer)
+                .or_else(|| x.as_f64().map(Value::Float)),
+            serde_json::Value::String(x) => Some(Value::String(x)),
+            serde_json::Value::Array(xs) => Some(Value::Array(xs.into_iter().filter_map(convert).collect())),
+            serde_json::Value::Object(xs) => Some(Value::Table(
+                xs.into_iter()
+                    .filter_map(|(k, v)| convert(v).map(|v| (k, v)))
+                    .collect(),
+            )),
+        }
+    }
+
+    convert(value).unwrap_or_else(|| Value::Table(Table::new()))
+}
 
-    fn parse_toml(content: &str) -> Result<Config> {
-        let config: Config = toml::from_str(content)?;
+/// Replaces the `consul` and `discover` sections by those of the named profile, if present.
+fn apply_profile(config: &mut Value, name: &str) -> Result<()> {
+    let profile = config
+        .get("profiles")
+        .and_then(|profiles| profiles.get(name))
+        .cloned()
+        .ok_or_else(|| Error::ProfileNotFound { name: name.to_string() })?;
+    if let Value::Table(ref mut config) = *config {
+        for section in &["consul", "discover"] {
+            if let Some(value) = profile.get(*section) {
+                config.insert(section.to_string(), value.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces all secrets of a config, e.g., the Consul token, by `REDACTED`.
+pub fn redact(config: &mut Value) {
+    fn redact_path(value: &mut Value, path: &[&str]) {
+        let (key, rest) = match path.split_first() {
+            Some(x) => x,
+            None => {
+                *value = Value::String(REDACTED.to_string());
+                return;
+            }
+        };
+        match *value {
+            Value::Table(ref mut table) if *key == "*" => {
+                for (_, value) in table.iter_mut() {
+                    redact_path(value, rest);
+                }
+            }
+            Value::Table(ref mut table) => {
+                if let Some(value) = table.get_mut(*key) {
+                    redact_path(value, rest);
+                }
+            }
+            Value::Array(ref mut values) if *key == "*" => {
+                for value in values {
+                    redact_path(value, rest);
+                }
+            }
+            _ => {}
+        }
+    }
 
-        Ok(config)
+    for path in SECRETS {
+        redact_path(config, path);
     }
 }
 
-error_chain! {
-    foreign_links {
-        CouldNotRead(::std::io::Error);
-        CouldNotParse(::toml::de::Error);
+/// Returns whether the environment variable overriding `key` in `section` holds a secret.
+pub fn is_secret_env_var(section: &str, key: &str) -> bool {
+    SECRETS.contains(&&[section, key][..])
+}
+
+/// Merges `layer` into `base`; tables are merged recursively while all other values, including
+/// arrays, are replaced.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (&mut Value::Table(ref mut base), Value::Table(layer)) => {
+            for (key, value) in layer {
+                if let Some(existing) = base.get_mut(&key) {
+                    merge(existing, value);
+                    continue;
+                }
+                base.insert(key, value);
+            }
+        }
+        (base, layer) => *base = layer,
     }
 }
+
+/// Returns the names of all `SW_*` environment variables that do not override a config value.
+pub fn unknown_env_vars() -> Vec<String> {
+    let known: Vec<_> = ENV_OVERRIDES
+        .iter()
+        .map(|&(section, key, _)| env_var_name(section, key))
+        .collect();
+    let mut unknown: Vec<_> = env::vars()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with("SW_") && !known.contains(name))
+        .collect();
+    unknown.sort();
+
+    unknown
+}
+
+fn env_layer() -> Value {
+    let mut layer = Table::new();
+    for &(section, key, kind) in ENV_OVERRIDES {
+        let name = env_var_name(section, key);
+        let value = match env::var(&name) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        debug!("Overriding {}.{} by environment variable {}", section, key, name);
+        let value = match kind {
+            EnvKind::String => Value::String(value),
+            EnvKind::List => Value::Array(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|x| !x.is_empty())
+                    .map(|x| Value::String(x.to_string()))
+                    .collect(),
+            ),
+        };
+        let section = layer
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if let Value::Table(ref mut section) = *section {
+            section.insert(key.to_string(), value);
+        }
+    }
+
+    Value::Table(layer)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Could not read config file '{}'", .file.display())]
+    Read { file: PathBuf, source: io::Error },
+
+    #[error("Invalid config file '{}' at '{path}'", .file.display())]
+    Parse {
+        file: PathBuf,
+        /// Path of the offending key, e.g., `consul.urls[0]`, or `.` for syntax errors.
+        path: String,
+        line: Option<usize>,
+        source: ParseError,
+    },
+
+    /// The config files are valid on their own, but environment variables set invalid values.
+    #[error("Invalid config at '{path}'")]
+    Merge { path: String, source: toml::de::Error },
+
+    /// The config is valid, but sw-present could not use it, e.g., because a template is broken.
+    #[error("Invalid config: {}", .problems.join("; "))]
+    Invalid { problems: Vec<String> },
+
+    #[error("Unknown config format '{format}'")]
+    UnknownFormat { format: String },
+
+    #[error("Profile '{name}' not found")]
+    ProfileNotFound { name: String },
+}
+
+impl Error {
+    fn parse<E: Into<ParseError>>(file: &Path, path: String, source: E) -> Error {
+        let source = source.into();
+        Error::Parse {
+            file: file.to_path_buf(),
+            path,
+            line: source.line(),
+            source,
+        }
+    }
+}
+
+/// Syntax or type error reported by the parser of a config format.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl ParseError {
+    /// Returns the 1-based line of the error, if the parser reports one.
+    pub fn line(&self) -> Option<usize> {
+        match *self {
+            ParseError::Toml(ref e) => e.line_col().map(|(line, _)| line + 1),
+            ParseError::Yaml(ref e) => e.location().map(|x| x.line()),
+            ParseError::Json(ref e) => Some(e.line()).filter(|&x| x > 0),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
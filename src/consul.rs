@@ -1,10 +1,17 @@
+use futures::future::Either;
 use futures::{future, Future, Stream};
-use hyper::{Client as HyperClient, Uri};
+use hyper::http;
+use hyper::http::uri::InvalidUri;
+use hyper::{self, Body, Chunk, Client as HyperClient, Request, Response, StatusCode, Uri};
+use metrics;
 use serde::de::DeserializeOwned;
 use serde_json;
+use serde_path_to_error;
 use std::collections::HashMap;
-use std::str;
-use tokio_core::reactor::Core;
+use std::io;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio_core::reactor::{Core, Timeout};
 
 trait Client {
     fn new(urls: Vec<String>) -> Result<Self>
@@ -13,112 +20,153 @@ trait Client {
     fn services(&mut self) -> Result<HashMap<String, Vec<String>>>;
     fn nodes(&mut self, services: &[&str]) -> Result<HashMap<String, Vec<Node>>>;
     fn healthy_nodes(&mut self, services: &[&str]) -> Result<HashMap<String, Vec<Health>>>;
+    fn critical_checks(&mut self) -> Result<Vec<HealthCheck>>;
 }
 
 #[derive(Debug)]
 pub struct SyncClient {
     urls: Vec<String>,
+    token: Option<String>,
     core: Core,
 }
 
 impl Client for SyncClient {
     fn new(urls: Vec<String>) -> Result<SyncClient> {
-        let core = Core::new().chain_err(|| ErrorKind::TokioError)?;
+        let core = Core::new().map_err(Error::Tokio)?;
 
-        Ok(SyncClient { urls, core })
+        Ok(SyncClient {
+            urls,
+            token: None,
+            core,
+        })
     }
 
     fn services(&mut self) -> Result<HashMap<String, Vec<String>>> {
-        let uri_str = format!("{}/v1/catalog/services", self.urls[0]);
-        let uri: Uri = uri_str.parse().chain_err(|| {
-            ErrorKind::ConsulError("could not parse url".to_string())
-        })?;
-        let hyper = HyperClient::new();
-        let call = hyper.get(uri).and_then(|res| res.into_body().concat2()).map(
-            |body| {
-                let json = str::from_utf8(&body).chain_err(|| {
-                    ErrorKind::ConsulError("Failed to read JSON".to_string())
-                })?;
-                let ss: HashMap<String, Vec<String>> = serde_json::from_str(json).chain_err(|| {
-                    ErrorKind::ConsulError("Failed to deserialize JSON".to_string())
-                })?;
-                Ok(ss)
-            },
-        );
-
-        self.core.run(call).chain_err(|| {
-            ErrorKind::ConsulError("failed to get services".to_string())
-        })?
+        self.get("/v1/catalog/services")
     }
 
     fn nodes(&mut self, services: &[&str]) -> Result<HashMap<String, Vec<Node>>> {
-        let base_uri = format!("{}/v1/catalog/service/@@", self.urls[0]);
-        consul_calls_by_services(&mut self.core, &base_uri, services)
+        let endpoint = "/v1/catalog/service";
+        let base_uri = format!("{}{}/@@", self.urls[0], endpoint);
+        consul_calls_by_services(&mut self.core, &base_uri, endpoint, self.token.as_deref(), services)
     }
 
     fn healthy_nodes(&mut self, services: &[&str]) -> Result<HashMap<String, Vec<Health>>> {
         // @@ is a place holder used in `consul_calls_by_services` to insert the service name into
         // this url
-        let base_uri = format!("{}/v1/health/service/@@?passing", self.urls[0]);
-        consul_calls_by_services(&mut self.core, &base_uri, services)
+        let endpoint = "/v1/health/service";
+        let base_uri = format!("{}{}/@@?passing", self.urls[0], endpoint);
+        consul_calls_by_services(&mut self.core, &base_uri, endpoint, self.token.as_deref(), services)
+    }
+
+    fn critical_checks(&mut self) -> Result<Vec<HealthCheck>> {
+        self.get("/v1/health/state/critical")
+    }
+}
+
+impl SyncClient {
+    /// Calls a single, service independent endpoint.
+    fn get<T: DeserializeOwned>(&mut self, endpoint: &str) -> Result<T> {
+        let url = format!("{}{}", self.urls[0], endpoint);
+        let request = get_request(&url, self.token.as_deref())?;
+        let start = Instant::now();
+        let call = HyperClient::new().request(request).and_then(with_status);
+
+        let result = match self.core.run(call) {
+            Ok((status, body)) => {
+                debug!("GET {} answered {} in {:?}", url, status, start.elapsed());
+                deserialize(&url, None, status, &body)
+            }
+            Err(e) => {
+                debug!("GET {} failed after {:?}: {}", url, start.elapsed(), e);
+                Err(Error::Http { url, source: e })
+            }
+        };
+        metrics::observe_consul_request(endpoint, start.elapsed(), result.is_ok());
+
+        result
+    }
+}
+
+/// Creates a GET request carrying the ACL token, if any, in the `X-Consul-Token` header.
+fn get_request(url: &str, token: Option<&str>) -> Result<Request<Body>> {
+    let uri: Uri = url.parse().map_err(|e| Error::InvalidUrl {
+        url: url.to_string(),
+        source: e,
+    })?;
+    let mut builder = Request::get(uri);
+    if let Some(token) = token {
+        builder.header("X-Consul-Token", token);
     }
+
+    builder.body(Body::empty()).map_err(|e| Error::Request {
+        url: url.to_string(),
+        source: e,
+    })
+}
+
+/// Reads the whole body of a response keeping its status for logging.
+fn with_status(res: Response<Body>) -> impl Future<Item = (StatusCode, Chunk), Error = hyper::Error> {
+    let status = res.status();
+    res.into_body().concat2().map(move |body| (status, body))
+}
+
+/// Deserializes the body of a successful answer; failures name the JSON path that did not match.
+fn deserialize<T: DeserializeOwned>(url: &str, service: Option<&str>, status: StatusCode, body: &[u8]) -> Result<T> {
+    if !status.is_success() {
+        return Err(Error::Status {
+            url: url.to_string(),
+            service: service.map(|x| x.to_string()),
+            status,
+        });
+    }
+
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| Error::Json {
+        url: url.to_string(),
+        service: service.map(|x| x.to_string()),
+        path: e.path().to_string(),
+        source: e.into_inner(),
+    })
 }
 
 fn consul_calls_by_services<T: DeserializeOwned>(
     core: &mut Core,
     uri_base: &str,
+    endpoint: &str,
+    token: Option<&str>,
     services: &[&str],
 ) -> Result<HashMap<String, Vec<T>>> {
-    let service_calls: Result<Vec<_>> = services
+    let calls = services
         .iter()
         .map(|service| {
-            let uri_str = uri_base.replace("@@", service);
-            let uri: Uri = uri_str.parse().chain_err(|| {
-                ErrorKind::ConsulError("could not parse url".to_string())
-            })?;
-            let hyper = HyperClient::new();
+            let url = uri_base.replace("@@", service);
+            let request = get_request(&url, token)?;
             let service_name = service.to_string();
-            let call = hyper.get(uri).and_then(|res| res.into_body().concat2()).map(
-                |body| {
-                    let json = str::from_utf8(&body).chain_err(|| {
-                        ErrorKind::ConsulError(format!(
-                            "Failed to read JSON for service '{}'",
-                            service_name
-                        ))
-                    })?;
-                    let ss: Vec<T> = serde_json::from_str(json).chain_err(|| {
-                        ErrorKind::ConsulError(format!(
-                            "Failed to deserialize JSON for service '{}'",
-                            service_name
-                        ))
-                    })?;
-                    Ok((service_name, ss))
+            let endpoint = endpoint.to_string();
+            let start = Instant::now();
+            let call = HyperClient::new().request(request).and_then(with_status).then(
+                move |answer| {
+                    let result = match answer {
+                        Ok((status, body)) => {
+                            debug!("GET {} answered {} in {:?}", url, status, start.elapsed());
+                            deserialize(&url, Some(&service_name), status, &body).map(|ss| (service_name, ss))
+                        }
+                        Err(e) => {
+                            debug!("GET {} failed after {:?}: {}", url, start.elapsed(), e);
+                            Err(Error::Http { url, source: e })
+                        }
+                    };
+                    metrics::observe_consul_request(&endpoint, start.elapsed(), result.is_ok());
+                    result
                 },
             );
             Ok(call)
         })
-        .collect();
-
-    service_calls
-        .map(|calls| {
-            let joined = future::join_all(calls)
-                .and_then(move |answers: Vec<Result<(String, Vec<_>)>>| {
-                    // Results are Ok((String, Vec<Node>);
-                    // Errs are not arrive here due to the semantics of join_all.
-                    let result: HashMap<String, Vec<T>> = answers
-                    .into_iter()
-                    .map(|x| x.unwrap()) // Safe
-                    .collect();
-
-                    future::ok(result)
-                })
-                .map_err(move |e| Error::with_chain(e, ErrorKind::TokioError));
-
-            core.run(joined)
-        })
-        .chain_err(|| {
-            ErrorKind::ConsulError("failed to get nodes for services".to_string())
-        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    core.run(future::join_all(calls))
+        .map(|answers| answers.into_iter().collect())
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -131,6 +179,8 @@ pub struct Node {
     pub meta_data: HashMap<String, String>,
     #[serde(rename = "Address")]
     pub address: String,
+    #[serde(rename = "Datacenter", default)]
+    pub datacenter: Option<String>,
     #[serde(rename = "ServicePort")]
     pub service_port: u16,
     #[serde(rename = "ServiceTags")]
@@ -139,6 +189,8 @@ pub struct Node {
     pub service_id: String,
     #[serde(rename = "ServiceName")]
     pub service_name: String,
+    #[serde(rename = "ServiceMeta", default)]
+    pub service_meta: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -157,11 +209,27 @@ pub struct HealthyNode {
     pub address: String,
 }
 
-#[derive(Debug, Serialize)]
+/// A critical health check of a service instance or, if `service_name` is empty, of a node.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HealthCheck {
+    #[serde(rename = "Node")]
+    pub node: String,
+    #[serde(rename = "CheckID")]
+    pub check_id: String,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "ServiceName", default)]
+    pub service_name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Catalog {
     pub services: HashMap<String, Vec<String>>,
     nodes_by_service: HashMap<String, Vec<Node>>,
     healthy_nodes_by_service: HashMap<String, Vec<Health>>,
+    // Missing in snapshots taken before critical checks were part of the catalog
+    #[serde(default)]
+    critical_checks: Vec<HealthCheck>,
 }
 
 impl Catalog {
@@ -180,24 +248,71 @@ impl Catalog {
     }
 
     pub fn is_node_healthy_for_service(&self, node: &Node, service_name: &str) -> bool {
-        self.healthy_nodes_by_service.get(service_name).map_or(
-            false,
-            |xs| {
-                // TODO: Fix me -- that is uncessary
-                let ids: Vec<_> = xs.iter().map(|x| x.node.id.clone()).collect();
-                ids.contains(&node.id)
-            },
-        )
+        self.healthy_nodes_by_service.get(service_name).is_some_and(|xs| {
+            xs.iter().any(|x| x.node.id == node.id)
+        })
+    }
+
+    /// Returns whether a check of the service instance or of the node itself is critical. Nodes
+    /// that are neither healthy nor critical only have warnings.
+    pub fn is_node_critical_for_service(&self, node: &Node, service_name: &str) -> bool {
+        self.critical_checks.iter().any(|x| {
+            x.node == node.name && (x.service_name.is_empty() || x.service_name == service_name)
+        })
     }
 }
 
 pub struct Consul {
     url: String,
+    token: Option<String>,
 }
 
 impl Consul {
     pub fn new(url: String) -> Self {
-        Consul { url }
+        Consul { url, token: None }
+    }
+
+    /// Creates a Consul client that authenticates with an ACL token.
+    pub fn with_token(url: String, token: String) -> Self {
+        Consul {
+            url,
+            token: Some(token),
+        }
+    }
+
+    /// Returns the address of the cluster leader unless Consul does not answer within `timeout`
+    /// or there is no leader.
+    pub fn leader(&self, timeout: Duration) -> Result<String> {
+        let endpoint = "/v1/status/leader";
+        let url = format!("{}{}", self.url, endpoint);
+        let mut core = Core::new().map_err(Error::Tokio)?;
+        let timer = Timeout::new(timeout, &core.handle()).map_err(Error::Tokio)?;
+        let request = get_request(&url, self.token.as_deref())?;
+        let call = HyperClient::new().request(request).and_then(with_status);
+
+        let start = Instant::now();
+        let result = core.run(call.select2(timer));
+        metrics::observe_consul_request(endpoint, start.elapsed(), matches!(result, Ok(Either::A(_))));
+
+        let (status, body) = match result {
+            Ok(Either::A((answer, _))) => answer,
+            Ok(Either::B(_)) => {
+                debug!("GET {} timed out after {:?}", url, start.elapsed());
+                return Err(Error::Timeout { url, timeout });
+            }
+            Err(Either::A((e, _))) => {
+                debug!("GET {} failed after {:?}: {}", url, start.elapsed(), e);
+                return Err(Error::Http { url, source: e });
+            }
+            Err(Either::B((e, _))) => return Err(Error::Tokio(e)),
+        };
+        debug!("GET {} answered {} in {:?}", url, status, start.elapsed());
+        let leader: String = deserialize(&url, None, status, &body)?;
+        if leader.is_empty() {
+            return Err(Error::NoLeader { url });
+        }
+
+        Ok(leader)
     }
 
     pub fn catalog(&self) -> Result<Catalog> {
@@ -210,6 +325,7 @@ impl Consul {
         tags: Option<Vec<String>>,
     ) -> Result<Catalog> {
         let mut client = SyncClient::new(vec![self.url.clone()])?;
+        client.token = self.token.clone();
 
         let service_filter: Box<dyn Fn(&String) -> bool> = if let Some(services) = services {
             Box::new(move |x| services.contains(x))
@@ -225,8 +341,8 @@ impl Consul {
 
         let services: HashMap<String, Vec<String>> = client.services().map(|h| {
             h.into_iter()
-                .filter(|&(ref key, _)| service_filter(key))
-                .filter(|&(_, ref values)| values.iter().any(|x| tag_filter(x)))
+                .filter(|(key, _)| service_filter(key))
+                .filter(|(_, values)| values.iter().any(&tag_filter))
                 .collect()
         })?;
 
@@ -237,7 +353,7 @@ impl Consul {
                     .map(|(service, values)| {
                         let v = values
                             .into_iter()
-                            .filter(|node| node.service_tags.iter().any(|x| tag_filter(x)))
+                            .filter(|node| node.service_tags.iter().any(&tag_filter))
                             .collect::<Vec<_>>();
                         (service, v)
                     })
@@ -250,24 +366,61 @@ impl Consul {
             client.healthy_nodes(&service_names)?
         };
 
-        Ok(Catalog {
+        let critical_checks = client
+            .critical_checks()?
+            .into_iter()
+            .filter(|x| x.service_name.is_empty() || services.contains_key(&x.service_name))
+            .collect();
+
+        let catalog = Catalog {
             services,
             nodes_by_service,
             healthy_nodes_by_service,
-        })
+            critical_checks,
+        };
+        metrics::observe_catalog(&catalog);
+
+        Ok(catalog)
     }
 }
 
-error_chain! {
-    errors {
-        TokioError {
-            description("Failed to use Tokio")
-            display("Failed to use Tokio")
-        }
-
-        ConsulError(cause: String) {
-            description("Failed get data from Consul")
-            display("Failed get data from Consul because {}", cause)
-        }
-    }
+/// Errors talking to Consul; all but `Tokio` name the URL of the failed request.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to use Tokio")]
+    Tokio(#[source] io::Error),
+
+    #[error("Invalid Consul URL '{url}'")]
+    InvalidUrl { url: String, source: InvalidUri },
+
+    #[error("Failed to create request for '{url}'")]
+    Request { url: String, source: http::Error },
+
+    #[error("Failed to get data from Consul at '{url}'")]
+    Http { url: String, source: hyper::Error },
+
+    #[error("Consul did not answer '{url}' within {}s", .timeout.as_secs())]
+    Timeout { url: String, timeout: Duration },
+
+    #[error("Consul answered '{url}' with {status}")]
+    Status {
+        url: String,
+        service: Option<String>,
+        status: StatusCode,
+    },
+
+    #[error("Failed to deserialize JSON from '{url}' at '{path}'")]
+    Json {
+        url: String,
+        service: Option<String>,
+        /// Path of the value that did not match, e.g., `[0].ServicePort`.
+        path: String,
+        source: serde_json::Error,
+    },
+
+    #[error("Consul cluster at '{url}' has no leader")]
+    NoLeader { url: String },
 }
+
+pub type Result<T> = ::std::result::Result<T, Error>;
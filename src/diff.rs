@@ -0,0 +1,184 @@
+use consul::{Catalog, Node};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Changes between two catalogs, e.g., a snapshot taken before a deployment and the live catalog.
+///
+/// Instances are identified by node name and service id.
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    pub added_services: Vec<String>,
+    pub removed_services: Vec<String>,
+    pub added_nodes: Vec<String>,
+    pub removed_nodes: Vec<String>,
+    pub changed_services: Vec<ServiceDiff>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ServiceDiff {
+    pub name: String,
+    pub added_tags: Vec<String>,
+    pub removed_tags: Vec<String>,
+    pub added_instances: Vec<Instance>,
+    pub removed_instances: Vec<Instance>,
+    pub changed_instances: Vec<InstanceDiff>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Instance {
+    pub node: String,
+    pub service_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstanceDiff {
+    pub node: String,
+    pub service_id: String,
+    pub address: Option<Change<String>>,
+    pub port: Option<Change<u16>>,
+    pub added_tags: Vec<String>,
+    pub removed_tags: Vec<String>,
+    pub healthy: Option<Change<bool>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn of(from: T, to: T) -> Option<Change<T>> {
+        if from == to {
+            None
+        } else {
+            Some(Change { from, to })
+        }
+    }
+}
+
+impl Diff {
+    pub fn between(old: &Catalog, new: &Catalog) -> Diff {
+        let old_services: BTreeSet<_> = old.services().into_iter().collect();
+        let new_services: BTreeSet<_> = new.services().into_iter().collect();
+        let old_nodes = node_names(old);
+        let new_nodes = node_names(new);
+
+        let changed_services = old_services
+            .intersection(&new_services)
+            .map(|name| service_diff(old, new, name))
+            .filter(|x| !x.is_empty())
+            .collect();
+
+        Diff {
+            added_services: difference(&new_services, &old_services),
+            removed_services: difference(&old_services, &new_services),
+            added_nodes: difference(&new_nodes, &old_nodes),
+            removed_nodes: difference(&old_nodes, &new_nodes),
+            changed_services,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_services.is_empty()
+            && self.removed_services.is_empty()
+            && self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.changed_services.is_empty()
+    }
+}
+
+impl ServiceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_tags.is_empty()
+            && self.removed_tags.is_empty()
+            && self.added_instances.is_empty()
+            && self.removed_instances.is_empty()
+            && self.changed_instances.is_empty()
+    }
+}
+
+impl InstanceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.address.is_none()
+            && self.port.is_none()
+            && self.added_tags.is_empty()
+            && self.removed_tags.is_empty()
+            && self.healthy.is_none()
+    }
+}
+
+fn service_diff(old: &Catalog, new: &Catalog, name: &str) -> ServiceDiff {
+    let old_tags: BTreeSet<_> = old.service_tags(name).unwrap_or_default().into_iter().collect();
+    let new_tags: BTreeSet<_> = new.service_tags(name).unwrap_or_default().into_iter().collect();
+    let old_instances = instances(old, name);
+    let new_instances = instances(new, name);
+
+    let added_instances = new_instances
+        .keys()
+        .filter(|key| !old_instances.contains_key(*key))
+        .map(|&(node, service_id)| Instance {
+            node: node.to_string(),
+            service_id: service_id.to_string(),
+        })
+        .collect();
+    let removed_instances = old_instances
+        .keys()
+        .filter(|key| !new_instances.contains_key(*key))
+        .map(|&(node, service_id)| Instance {
+            node: node.to_string(),
+            service_id: service_id.to_string(),
+        })
+        .collect();
+    let changed_instances = old_instances
+        .iter()
+        .filter_map(|(key, old_node)| new_instances.get(key).map(|new_node| (old_node, new_node)))
+        .map(|(old_node, new_node)| {
+            let old_tags: BTreeSet<_> = old_node.service_tags.iter().collect();
+            let new_tags: BTreeSet<_> = new_node.service_tags.iter().collect();
+            InstanceDiff {
+                node: new_node.name.clone(),
+                service_id: new_node.service_id.clone(),
+                address: Change::of(old_node.address.clone(), new_node.address.clone()),
+                port: Change::of(old_node.service_port, new_node.service_port),
+                added_tags: difference(&new_tags, &old_tags),
+                removed_tags: difference(&old_tags, &new_tags),
+                healthy: Change::of(
+                    old.is_node_healthy_for_service(old_node, name),
+                    new.is_node_healthy_for_service(new_node, name),
+                ),
+            }
+        })
+        .filter(|x| !x.is_empty())
+        .collect();
+
+    ServiceDiff {
+        name: name.to_string(),
+        added_tags: difference(&new_tags, &old_tags),
+        removed_tags: difference(&old_tags, &new_tags),
+        added_instances,
+        removed_instances,
+        changed_instances,
+    }
+}
+
+fn instances<'a>(catalog: &'a Catalog, service_name: &str) -> BTreeMap<(&'a str, &'a str), &'a Node> {
+    catalog
+        .nodes_by_service(service_name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|node| ((node.name.as_str(), node.service_id.as_str()), node))
+        .collect()
+}
+
+fn node_names(catalog: &Catalog) -> BTreeSet<&String> {
+    catalog
+        .services()
+        .into_iter()
+        .flat_map(|name| catalog.nodes_by_service(name).unwrap_or_default())
+        .map(|node| &node.name)
+        .collect()
+}
+
+fn difference(a: &BTreeSet<&String>, b: &BTreeSet<&String>) -> Vec<String> {
+    a.difference(b).map(|x| x.to_string()).collect()
+}
@@ -0,0 +1,271 @@
+use consul::Catalog;
+use csv;
+use handlebars;
+use present;
+use serde_json;
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::io::Write;
+use thiserror::Error;
+
+/// Version of the output model; bump whenever a field is renamed, removed or changes its meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Names of the output modules of sw-discover as used on the command line and in `discover.output`.
+pub const OUTPUT_MODULES: &[&str] = &[
+    "terminal", "details", "json", "yaml", "csv", "tsv", "prometheus", "template",
+];
+
+/// Names of the templates shipped with this crate, see `builtin_template`.
+pub const BUILTIN_TEMPLATES: &[&str] = &["haproxy", "nginx", "hosts"];
+
+const HAPROXY_TEMPLATE: &str = "\
+{{#each services~}}
+backend {{name}}
+    balance roundrobin
+{{~#each instances}}
+    server {{node}} {{address}}:{{port}} check{{#unless healthy}} disabled{{/unless}}
+{{~/each}}
+
+{{/each}}";
+
+const NGINX_TEMPLATE: &str = "\
+{{#each services~}}
+upstream {{name}} {
+{{~#each instances}}
+    server {{address}}:{{port}}{{#unless healthy}} down{{/unless}};
+{{~/each}}
+}
+
+{{/each}}";
+
+const HOSTS_TEMPLATE: &str = "\
+{{#each services}}{{#each instances}}{{address}}\t{{node}}\t# {{../name}}
+{{/each}}{{/each}}";
+
+/// Returns the source of a built-in template by its name.
+pub fn builtin_template(name: &str) -> Option<&'static str> {
+    match name {
+        "haproxy" => Some(HAPROXY_TEMPLATE),
+        "nginx" => Some(NGINX_TEMPLATE),
+        "hosts" => Some(HOSTS_TEMPLATE),
+        _ => None,
+    }
+}
+
+/// Stable, documented view of a `Catalog` for machine-readable output.
+///
+/// Services and instances are sorted by name so that two reports of the same catalog are
+/// byte-identical and can be diffed.
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub version: u32,
+    pub services: Vec<Service<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Service<'a> {
+    pub name: &'a str,
+    pub tags: Vec<&'a str>,
+    pub instances: Vec<Instance<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Instance<'a> {
+    pub node: &'a str,
+    pub node_id: &'a str,
+    pub address: &'a str,
+    pub datacenter: Option<&'a str>,
+    pub port: u16,
+    pub service_id: &'a str,
+    pub tags: Vec<&'a str>,
+    pub meta: BTreeMap<&'a str, &'a str>,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Row<'a> {
+    service: &'a str,
+    node: &'a str,
+    node_id: &'a str,
+    address: &'a str,
+    port: u16,
+    service_id: &'a str,
+    tags: String,
+    healthy: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetGroup {
+    targets: Vec<String>,
+    labels: BTreeMap<&'static str, String>,
+}
+
+impl<'a> Report<'a> {
+    pub fn from_catalog(catalog: &'a Catalog) -> Report<'a> {
+        let mut services: Vec<_> = catalog
+            .services()
+            .into_iter()
+            .map(|name| {
+                let mut tags: Vec<_> = catalog
+                    .service_tags(name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|x| x.as_str())
+                    .collect();
+                tags.sort_unstable();
+
+                let mut instances: Vec<_> = catalog
+                    .nodes_by_service(name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|node| {
+                        let mut tags: Vec<_> =
+                            node.service_tags.iter().map(|x| x.as_str()).collect();
+                        tags.sort_unstable();
+                        Instance {
+                            node: &node.name,
+                            node_id: &node.id,
+                            address: &node.address,
+                            datacenter: node.datacenter.as_deref(),
+                            port: node.service_port,
+                            service_id: &node.service_id,
+                            tags,
+                            meta: node
+                                .meta_data
+                                .iter()
+                                .map(|(k, v)| (k.as_str(), v.as_str()))
+                                .collect(),
+                            healthy: catalog.is_node_healthy_for_service(node, name),
+                        }
+                    })
+                    .collect();
+                instances.sort_by(|a, b| (a.node, a.service_id).cmp(&(b.node, b.service_id)));
+
+                Service {
+                    name,
+                    tags,
+                    instances,
+                }
+            })
+            .collect();
+        services.sort_by_key(|x| x.name);
+
+        Report {
+            version: SCHEMA_VERSION,
+            services,
+        }
+    }
+
+    pub fn write_json(&self, w: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *w, self)
+            .map_err(output_error("json"))?;
+        writeln!(w).map_err(output_error("json"))
+    }
+
+    pub fn write_yaml(&self, w: &mut dyn Write) -> Result<()> {
+        // serde_yaml ends documents with a newline already
+        serde_yaml::to_writer(w, self).map_err(output_error("yaml"))
+    }
+
+    /// Writes one row per instance; multiple tags are joined by `,` within a single column.
+    pub fn write_csv(&self, w: &mut dyn Write) -> Result<()> {
+        self.write_delimited(w, b',', "csv")
+    }
+
+    pub fn write_tsv(&self, w: &mut dyn Write) -> Result<()> {
+        self.write_delimited(w, b'\t', "tsv")
+    }
+
+    /// Writes a Prometheus `file_sd_configs` target list with one target group per instance.
+    ///
+    /// Tags are rendered as `,tag1,tag2,` just like Prometheus' own Consul service discovery does
+    /// so that relabeling rules can match on `.*,tag,.*`.
+    pub fn write_file_sd(&self, w: &mut dyn Write, healthy_only: bool) -> Result<()> {
+        let groups: Vec<_> = self
+            .services
+            .iter()
+            .flat_map(|service| {
+                service
+                    .instances
+                    .iter()
+                    .map(move |instance| (service, instance))
+            })
+            .filter(|&(_, instance)| !healthy_only || instance.healthy)
+            .map(|(service, instance)| {
+                let mut labels = BTreeMap::new();
+                labels.insert("service", service.name.to_string());
+                labels.insert("node", instance.node.to_string());
+                labels.insert("tags", format!(",{},", instance.tags.join(",")));
+                labels.insert("healthy", instance.healthy.to_string());
+                if let Some(datacenter) = instance.datacenter {
+                    labels.insert("datacenter", datacenter.to_string());
+                }
+                TargetGroup {
+                    targets: vec![format!("{}:{}", instance.address, instance.port)],
+                    labels,
+                }
+            })
+            .collect();
+
+        serde_json::to_writer_pretty(&mut *w, &groups)
+            .map_err(output_error("prometheus"))?;
+        writeln!(w).map_err(output_error("prometheus"))
+    }
+
+    /// Renders a Handlebars template against this report. Output is not HTML escaped.
+    pub fn render_template(&self, template: &str, w: &mut dyn Write) -> Result<()> {
+        let mut handlebars = present::handlebars();
+        handlebars.register_escape_fn(handlebars::no_escape);
+
+        handlebars
+            .render_template_to_write(template, self, w)
+            .map_err(output_error("template"))
+    }
+
+    fn write_delimited(&self, w: &mut dyn Write, delimiter: u8, format: &str) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(w);
+        for service in &self.services {
+            for instance in &service.instances {
+                let row = Row {
+                    service: service.name,
+                    node: instance.node,
+                    node_id: instance.node_id,
+                    address: instance.address,
+                    port: instance.port,
+                    service_id: instance.service_id,
+                    tags: instance.tags.join(","),
+                    healthy: instance.healthy,
+                };
+                writer
+                    .serialize(row)
+                    .map_err(output_error(format))?;
+            }
+        }
+        writer
+            .flush()
+            .map_err(output_error(format))
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to write output as {format}")]
+    Output {
+        format: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+}
+
+fn output_error<E: Into<Box<dyn StdError + Send + Sync>>>(format: &str) -> impl FnOnce(E) -> Error + '_ {
+    move |e| Error::Output {
+        format: format.to_string(),
+        source: e.into(),
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
@@ -1,15 +1,37 @@
-#[macro_use]
-extern crate error_chain;
+extern crate base64;
+extern crate bcrypt;
+extern crate csv;
 extern crate futures;
+#[macro_use]
 extern crate handlebars;
 extern crate hyper;
+extern crate hyper_rustls;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
+extern crate rand;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_path_to_error;
+extern crate serde_yaml;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_ignored;
+extern crate sha2;
+extern crate thiserror;
 extern crate tokio_core;
 extern crate toml;
+extern crate url;
 
+pub mod auth;
+pub mod check;
 pub mod config;
 pub mod consul;
+pub mod diff;
+pub mod discover;
+pub mod logging;
+pub mod metrics;
 pub mod present;
+pub mod readiness;
+pub mod validate;
@@ -0,0 +1,183 @@
+use log::{self, LevelFilter, Log, Metadata, Record};
+use rand::{self, Rng};
+use serde_json;
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Crates whose messages honor the verbosity; all other crates log only warnings and errors
+/// unless the verbosity is at its maximum.
+const OWN_TARGETS: &[&str] = &["service_world", "sw_present", "sw_discover"];
+
+thread_local! {
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<LogFormat> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(Error::UnknownLogFormat { format: s.to_string() }),
+        }
+    }
+}
+
+/// Logs to stderr; `verbosity` is the number of `-v` flags, i.e., 0 logs warnings and errors, 1
+/// adds infos, 2 adds debug messages, e.g., Consul requests, and 3 traces all crates.
+pub fn init(verbosity: u64, format: LogFormat) -> Result<()> {
+    let (own_level, other_level) = match verbosity {
+        0 => (LevelFilter::Warn, LevelFilter::Warn),
+        1 => (LevelFilter::Info, LevelFilter::Warn),
+        2 => (LevelFilter::Debug, LevelFilter::Warn),
+        _ => (LevelFilter::Trace, LevelFilter::Trace),
+    };
+    let logger = Logger {
+        own_level,
+        other_level,
+        format,
+    };
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(cmp::max(own_level, other_level));
+
+    Ok(())
+}
+
+/// Tags all messages the current thread logs with `request_id` until reset by `None`.
+pub fn set_request_id(request_id: Option<String>) {
+    REQUEST_ID.with(|x| *x.borrow_mut() = request_id);
+}
+
+/// Returns a random request id.
+pub fn new_request_id() -> String {
+    let id: u64 = rand::thread_rng().gen();
+    format!("{:016x}", id)
+}
+
+struct Logger {
+    own_level: LevelFilter,
+    other_level: LevelFilter,
+    format: LogFormat,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: &'a str,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let target = metadata.target();
+        let own = OWN_TARGETS
+            .iter()
+            .any(|x| target == *x || (target.starts_with(x) && target[x.len()..].starts_with("::")));
+        let level = if own { self.own_level } else { self.other_level };
+
+        metadata.level() <= level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let timestamp = Timestamp(SystemTime::now()).to_string();
+        let line = REQUEST_ID.with(|request_id| {
+            let request_id = request_id.borrow();
+            match self.format {
+                LogFormat::Text => match *request_id {
+                    Some(ref id) => format!(
+                        "{} {:5} {} [{}] {}",
+                        timestamp,
+                        record.level(),
+                        record.target(),
+                        id,
+                        record.args()
+                    ),
+                    None => format!("{} {:5} {} {}", timestamp, record.level(), record.target(), record.args()),
+                },
+                LogFormat::Json => {
+                    let level = record.level().to_string();
+                    let json = JsonRecord {
+                        timestamp: &timestamp,
+                        level: &level,
+                        target: record.target(),
+                        message: record.args().to_string(),
+                        request_id: request_id.as_deref(),
+                    };
+                    serde_json::to_string(&json).unwrap_or_default()
+                }
+            }
+        });
+
+        let stderr = io::stderr();
+        let _ = writeln!(stderr.lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Formats a point in time as RFC 3339 in UTC with milliseconds.
+pub struct Timestamp(pub SystemTime);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let since_epoch = self.0.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (hour, minute, second) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+        // Converts days since the epoch to a civil date, cf. http://howardhinnant.github.io/date_algorithms.html
+        let days = (secs / 86_400) as i64 + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            since_epoch.subsec_millis()
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Unknown log format '{format}'")]
+    UnknownLogFormat { format: String },
+
+    #[error("Failed to initialize logging")]
+    Logger(#[from] log::SetLoggerError),
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
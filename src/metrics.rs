@@ -0,0 +1,195 @@
+use consul::Catalog;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the latency histograms in seconds.
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+lazy_static! {
+    static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics::default());
+}
+
+#[derive(Default)]
+struct Metrics {
+    /// Requests by route and status code.
+    requests: BTreeMap<(String, u16), u64>,
+    request_durations: BTreeMap<String, Histogram>,
+    consul_requests: BTreeMap<String, u64>,
+    consul_errors: BTreeMap<String, u64>,
+    consul_durations: BTreeMap<String, Histogram>,
+    render_durations: BTreeMap<String, Histogram>,
+    template_cache_hits: u64,
+    template_cache_misses: u64,
+    catalog: Option<CatalogStats>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Number of observations up to the corresponding bound of `BUCKETS`.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+struct CatalogStats {
+    services: usize,
+    instances: usize,
+    unhealthy_instances: usize,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration_seconds(duration);
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn write(&self, w: &mut dyn Write, name: &str, labels: &str) -> io::Result<()> {
+        for (count, bound) in self.buckets.iter().zip(BUCKETS) {
+            writeln!(w, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count)?;
+        }
+        writeln!(w, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count)?;
+        writeln!(w, "{}_sum{{{}}} {}", name, labels, self.sum)?;
+        writeln!(w, "{}_count{{{}}} {}", name, labels, self.count)
+    }
+}
+
+/// Records an HTTP request served by sw-present.
+pub fn observe_request(route: &str, status: u16, duration: Duration) {
+    let mut metrics = lock();
+    *metrics.requests.entry((route.to_string(), status)).or_insert(0) += 1;
+    metrics.request_durations.entry(route.to_string()).or_default().observe(duration);
+}
+
+/// Records a request to a Consul endpoint, e.g., `/v1/catalog/services`.
+pub fn observe_consul_request(endpoint: &str, duration: Duration, success: bool) {
+    let mut metrics = lock();
+    *metrics.consul_requests.entry(endpoint.to_string()).or_insert(0) += 1;
+    if !success {
+        *metrics.consul_errors.entry(endpoint.to_string()).or_insert(0) += 1;
+    }
+    metrics.consul_durations.entry(endpoint.to_string()).or_default().observe(duration);
+}
+
+/// Records the time to render a page template.
+pub fn observe_render(template_name: &str, duration: Duration) {
+    lock().render_durations.entry(template_name.to_string()).or_default().observe(duration);
+}
+
+/// Records whether a compiled page template could be reused or the file had to be compiled.
+pub fn observe_template_cache(hit: bool) {
+    let mut metrics = lock();
+    if hit {
+        metrics.template_cache_hits += 1;
+    } else {
+        metrics.template_cache_misses += 1;
+    }
+}
+
+/// Remembers the size and health of the last fetched catalog.
+pub fn observe_catalog(catalog: &Catalog) {
+    let services = catalog.services();
+    let mut instances = 0;
+    let mut unhealthy_instances = 0;
+    for service in &services {
+        for node in catalog.nodes_by_service(service).unwrap_or_default() {
+            instances += 1;
+            if !catalog.is_node_healthy_for_service(node, service) {
+                unhealthy_instances += 1;
+            }
+        }
+    }
+
+    lock().catalog = Some(CatalogStats {
+        services: services.len(),
+        instances,
+        unhealthy_instances,
+    });
+}
+
+/// Writes all metrics in the Prometheus text format.
+pub fn write(w: &mut dyn Write) -> io::Result<()> {
+    let metrics = lock();
+
+    writeln!(w, "# HELP sw_present_http_requests_total HTTP requests by route and status.")?;
+    writeln!(w, "# TYPE sw_present_http_requests_total counter")?;
+    for (&(ref route, status), count) in &metrics.requests {
+        writeln!(w, "sw_present_http_requests_total{{route=\"{}\",status=\"{}\"}} {}", escape(route), status, count)?;
+    }
+    writeln!(w, "# HELP sw_present_http_request_duration_seconds HTTP request latencies by route.")?;
+    writeln!(w, "# TYPE sw_present_http_request_duration_seconds histogram")?;
+    for (route, histogram) in &metrics.request_durations {
+        let labels = format!("route=\"{}\"", escape(route));
+        histogram.write(w, "sw_present_http_request_duration_seconds", &labels)?;
+    }
+
+    writeln!(w, "# HELP sw_present_consul_requests_total Requests to Consul by endpoint.")?;
+    writeln!(w, "# TYPE sw_present_consul_requests_total counter")?;
+    for (endpoint, count) in &metrics.consul_requests {
+        writeln!(w, "sw_present_consul_requests_total{{endpoint=\"{}\"}} {}", escape(endpoint), count)?;
+    }
+    writeln!(w, "# HELP sw_present_consul_request_errors_total Failed requests to Consul by endpoint.")?;
+    writeln!(w, "# TYPE sw_present_consul_request_errors_total counter")?;
+    for endpoint in metrics.consul_requests.keys() {
+        let count = metrics.consul_errors.get(endpoint).cloned().unwrap_or(0);
+        writeln!(w, "sw_present_consul_request_errors_total{{endpoint=\"{}\"}} {}", escape(endpoint), count)?;
+    }
+    writeln!(w, "# HELP sw_present_consul_request_duration_seconds Consul request latencies by endpoint.")?;
+    writeln!(w, "# TYPE sw_present_consul_request_duration_seconds histogram")?;
+    for (endpoint, histogram) in &metrics.consul_durations {
+        let labels = format!("endpoint=\"{}\"", escape(endpoint));
+        histogram.write(w, "sw_present_consul_request_duration_seconds", &labels)?;
+    }
+
+    writeln!(w, "# HELP sw_present_template_render_duration_seconds Template render times by template.")?;
+    writeln!(w, "# TYPE sw_present_template_render_duration_seconds histogram")?;
+    for (template_name, histogram) in &metrics.render_durations {
+        let labels = format!("template=\"{}\"", escape(template_name));
+        histogram.write(w, "sw_present_template_render_duration_seconds", &labels)?;
+    }
+    writeln!(w, "# HELP sw_present_template_cache_hits_total Page templates reused from the cache.")?;
+    writeln!(w, "# TYPE sw_present_template_cache_hits_total counter")?;
+    writeln!(w, "sw_present_template_cache_hits_total {}", metrics.template_cache_hits)?;
+    writeln!(w, "# HELP sw_present_template_cache_misses_total Page templates compiled because they were new or changed.")?;
+    writeln!(w, "# TYPE sw_present_template_cache_misses_total counter")?;
+    writeln!(w, "sw_present_template_cache_misses_total {}", metrics.template_cache_misses)?;
+
+    if let Some(ref catalog) = metrics.catalog {
+        let gauges = [
+            ("services", "Services", catalog.services),
+            ("instances", "Service instances", catalog.instances),
+            ("unhealthy_instances", "Service instances failing health checks", catalog.unhealthy_instances),
+        ];
+        for &(name, help, value) in &gauges {
+            writeln!(w, "# HELP sw_present_catalog_{} {} in the last fetched catalog.", name, help)?;
+            writeln!(w, "# TYPE sw_present_catalog_{} gauge", name)?;
+            writeln!(w, "sw_present_catalog_{} {}", name, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn lock() -> ::std::sync::MutexGuard<'static, Metrics> {
+    // Metrics stay usable even if a thread panicked while holding the lock.
+    METRICS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn duration_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// Escapes a label value as required by the Prometheus text format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
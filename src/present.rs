@@ -1,23 +1,270 @@
-use config::Config;
+use config::{self, AccessRule, Condition, Config, GroupBy, ServiceInfo, Target, EVERYONE_GROUP};
 use consul::{self, Consul, Catalog};
-use handlebars::Handlebars;
-use std::collections::HashMap;
-use std::io::Write;
+use handlebars::{self, Handlebars, RenderError, Template, TemplateFileError, TemplateRenderError};
+use metrics;
+use serde_json::{self, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use thiserror::Error;
+
+/// Name of the group collecting all services that belong to no other group.
+pub const OTHER_GROUP: &str = "Other";
+
+lazy_static! {
+    /// Compiled page templates by template name and file.
+    static ref TEMPLATES: Mutex<HashMap<(String, String), CachedTemplate>> = Mutex::new(HashMap::new());
+}
+
+struct CachedTemplate {
+    /// Modification time and size of the file when it was compiled.
+    modified: SystemTime,
+    len: u64,
+    template: Template,
+}
+
+/// Decides which services a user may see according to `[access]`.
+pub struct Visibility<'a> {
+    /// Rules of the user's groups; `None` if access is not restricted.
+    rules: Option<Vec<&'a AccessRule>>,
+}
+
+impl<'a> Visibility<'a> {
+    /// Returns the visibility for the given user, or an anonymous one for `None`.
+    pub fn for_user(config: &'a Config, user: Option<&str>) -> Visibility<'a> {
+        let access = &config.access;
+        if access.groups.is_empty() {
+            return Visibility { rules: None };
+        }
+
+        let user_groups: Vec<_> = user
+            .and_then(|user| access.users.get(user))
+            .map(|groups| groups.iter().map(|x| x.as_str()).collect())
+            .unwrap_or_default();
+        let rules = user_groups
+            .into_iter()
+            .chain(Some(EVERYONE_GROUP))
+            .filter_map(|group| access.groups.get(group))
+            .collect();
+
+        Visibility { rules: Some(rules) }
+    }
+
+    pub fn allows<T: AsRef<str>>(&self, service_name: &str, tags: &[T]) -> bool {
+        match self.rules {
+            None => true,
+            Some(ref rules) => rules.iter().any(|rule| {
+                rule.services.iter().any(|pattern| matches_pattern(pattern, service_name))
+                    || tags.iter().any(|tag| rule.tags.iter().any(|x| x == tag.as_ref()))
+            }),
+        }
+    }
+}
+
+/// Returns whether `text` matches `pattern` in which `*` matches any text.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !text.starts_with(first) {
+        return false;
+    }
+    let mut rest = &text[first.len()..];
+    let parts: Vec<_> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(x) => x,
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// Data of the start page; deliberately a small part of the config as templates must not see
+/// secrets like password hashes or the Consul token.
+#[derive(Serialize)]
+pub struct Index<'a> {
+    pub project_name: &'a str,
+    pub index_links: &'a [config::Service],
+    /// Link templates of the services the user may see.
+    pub services: BTreeMap<&'a str, &'a [config::Service]>,
+    /// Infos of the services the user may see.
+    pub service_info: BTreeMap<&'a str, &'a ServiceInfo>,
+}
+
+impl<'a> Index<'a> {
+    /// As the start page does not query Consul, only the service name patterns of `[access]`
+    /// apply.
+    pub fn from_config(config: &'a Config, visibility: &Visibility) -> Index<'a> {
+        let visible = |name: &str| visibility.allows::<&str>(name, &[]);
+
+        Index {
+            project_name: &config.general.project_name,
+            index_links: &config.present.index_links,
+            services: config
+                .services
+                .iter()
+                .filter(|&(name, _)| visible(name))
+                .map(|(name, links)| (name.as_str(), links.as_slice()))
+                .collect(),
+            service_info: config
+                .service_info
+                .iter()
+                .filter(|&(name, _)| visible(name))
+                .map(|(name, info)| (name.as_str(), info))
+                .collect(),
+        }
+    }
+}
 
 #[derive(Serialize)]
 pub struct Services<'a> {
     pub project_name: &'a str,
+    /// All services sorted by name.
     pub services: Vec<Service<'a>>,
+    /// Services grouped according to `present.grouping`; empty unless grouping is enabled.
+    pub groups: Vec<Group<'a>>,
+    /// The query the services have been filtered and sorted by.
+    pub query: &'a Query,
+}
+
+/// Filters and sorts the services page, e.g., by the query parameters of `/services`.
+#[derive(Debug, Default, Serialize)]
+pub struct Query {
+    /// Matches services whose name, display name, tags, or node names contain this text
+    /// ignoring case.
+    pub q: Option<String>,
+    /// Matches services tagged with this tag.
+    pub tag: Option<String>,
+    pub health: Option<Health>,
+    pub sort: Sort,
+}
+
+/// Matches services with all nodes passing or at least one node failing, respectively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    Passing,
+    Failing,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// Sorts alphabetically by name.
+    #[default]
+    Name,
+    /// Sorts by number of nodes, most first.
+    Nodes,
+    /// Sorts by number of unhealthy nodes, most first.
+    Unhealthy,
+}
+
+impl FromStr for Health {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Health> {
+        match s {
+            "passing" => Ok(Health::Passing),
+            "failing" => Ok(Health::Failing),
+            _ => Err(Error::InvalidQuery {
+                param: "health".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Sort> {
+        match s {
+            "name" => Ok(Sort::Name),
+            "nodes" => Ok(Sort::Nodes),
+            "unhealthy" => Ok(Sort::Unhealthy),
+            _ => Err(Error::InvalidQuery {
+                param: "sort".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl Query {
+    /// Creates a query from optional query parameters; empty parameters are ignored so that
+    /// forms may submit them.
+    pub fn from_params(
+        q: Option<String>,
+        tag: Option<String>,
+        health: Option<String>,
+        sort: Option<String>,
+    ) -> Result<Query> {
+        let non_empty = |x: Option<String>| x.filter(|x| !x.is_empty());
+
+        Ok(Query {
+            q: non_empty(q),
+            tag: non_empty(tag),
+            health: match non_empty(health) {
+                Some(health) => Some(health.parse()?),
+                None => None,
+            },
+            sort: match non_empty(sort) {
+                Some(sort) => sort.parse()?,
+                None => Sort::Name,
+            },
+        })
+    }
+
+    fn matches(&self, service: &Service) -> bool {
+        let text = self.q.as_ref().map(|x| x.to_lowercase());
+        let matches_text = |x: &str| text.as_ref().is_none_or(|text| x.to_lowercase().contains(text));
+        let matches_q = matches_text(service.name)
+            || service.info.display_name.as_deref().is_some_and(matches_text)
+            || service.tags.iter().any(|x| matches_text(x))
+            || service.nodes.iter().any(|x| matches_text(x.name));
+        let matches_tag = self.tag.as_ref().is_none_or(|tag| service.tags.contains(&tag));
+        let matches_health = match self.health {
+            Some(Health::Passing) => service.nodes.iter().all(|x| x.healthy),
+            Some(Health::Failing) => service.nodes.iter().any(|x| !x.healthy),
+            None => true,
+        };
+
+        matches_q && matches_tag && matches_health
+    }
+
+    fn sort(&self, services: &mut [Service]) {
+        let unhealthy = |service: &Service| service.nodes.iter().filter(|x| !x.healthy).count();
+        match self.sort {
+            Sort::Name => services.sort_by_key(|x| x.name),
+            Sort::Nodes => services.sort_by(|a, b| b.nodes.len().cmp(&a.nodes.len()).then(a.name.cmp(b.name))),
+            Sort::Unhealthy => services.sort_by(|a, b| unhealthy(b).cmp(&unhealthy(a)).then(a.name.cmp(b.name))),
+        }
+    }
 }
 
 #[derive(Serialize)]
+pub struct Group<'a> {
+    pub name: String,
+    pub services: Vec<Service<'a>>,
+}
+
+#[derive(Clone, Serialize)]
 pub struct Service<'a> {
     pub name: &'a str,
     pub tags: Vec<&'a String>,
     pub nodes: Vec<Node<'a>>,
+    pub info: ServiceInfo,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Node<'a> {
     pub name: &'a str,
     pub address: &'a str,
@@ -25,26 +272,53 @@ pub struct Node<'a> {
     pub service_tags: &'a Vec<String>,
     pub healthy: bool,
     pub service_urls: Option<HashMap<String, String>>,
+    pub service_links: Vec<Link>,
     pub default_url: Option<String>,
 }
 
+/// A link rendered from a link template in `[services]` for a single node.
+#[derive(Clone, Debug, Serialize)]
+pub struct Link {
+    pub name: String,
+    pub url: String,
+    pub icon: Option<String>,
+    pub description: Option<String>,
+    /// Value of the HTML `target` attribute, i.e., `_self` or `_blank`.
+    pub target: &'static str,
+}
+
 impl<'a> Services<'a> {
-    pub fn from_catalog(catalog: &'a Catalog, config: &'a Config) -> Result<Services<'a>> {
+    pub fn from_catalog(
+        catalog: &'a Catalog,
+        config: &'a Config,
+        query: &'a Query,
+        visibility: &Visibility,
+    ) -> Result<Services<'a>> {
         let mut services: Vec<_> = catalog
             .services()
             .iter()
+            .filter(|name| visibility.allows(name, &catalog.service_tags(name).unwrap_or_default()))
             .map(|name| {
+                let info = service_info(config, name, &catalog.nodes_by_service(name).unwrap_or_default());
                 let nodes = if let Some(nodes) = catalog.nodes_by_service(name) {
                     nodes
                         .into_iter()
                         .map(|node| {
                             let healthy = catalog.is_node_healthy_for_service(node, name);
-                            let mut service_urls = generate_service_ulrs(config, name, node).ok();
-                            let default_url = if let Some(ref mut s_urls) = service_urls {
-                                s_urls.remove("default")
-                            } else {
-                                None
-                            };
+                            let service_links = generate_service_links(config, name, node).unwrap_or_else(|e| {
+                                warn!("Could not render links of service '{}': {}", name, e);
+                                Vec::new()
+                            });
+                            let mut service_urls: HashMap<_, _> = service_links
+                                .iter()
+                                .map(|link| (link.name.to_string(), link.url.to_string()))
+                                .collect();
+                            let default_url = service_urls.remove("default");
+                            let service_links = service_links
+                                .into_iter()
+                                .filter(|link| link.name != "default")
+                                .collect();
+                            let service_urls = Some(service_urls);
                             Node {
                                 name: &node.name,
                                 address: &node.address,
@@ -52,6 +326,7 @@ impl<'a> Services<'a> {
                                 service_tags: &node.service_tags,
                                 healthy,
                                 service_urls,
+                                service_links,
                                 default_url,
                             }
                         })
@@ -59,37 +334,156 @@ impl<'a> Services<'a> {
                 } else {
                     Vec::new()
                 };
-                let tags = catalog.service_tags(name).unwrap_or_else(Vec::new);
-                Service { name, tags, nodes }
+                let tags = catalog.service_tags(name).unwrap_or_default();
+                Service {
+                    name,
+                    tags,
+                    nodes,
+                    info,
+                }
             })
+            .filter(|service| query.matches(service))
             .collect();
-        services.sort_by_key(|x| x.name);
+        query.sort(&mut services);
+        let groups = group(&services, catalog, config);
 
         Ok(Services {
             project_name: &config.general.project_name,
             services,
+            groups,
+            query,
         })
     }
 
-    pub fn render(&self, template_file: &str, mut w: &mut dyn Write) -> Result<()> {
-        let mut handlebars = Handlebars::new();
-        handlebars.register_helper("len", Box::new(handlebars_helper::vec_len_formatter));
+    pub fn render(&self, template_file: &str, w: &mut dyn Write) -> Result<()> {
+        let mut handlebars = handlebars();
 
         let template_name = "service_overview";
+        let start = Instant::now();
+        register_template_file(&mut handlebars, template_name, template_file)?;
         handlebars
-            .register_template_file(template_name, template_file)
-            .chain_err(|| ErrorKind::TemplateError(template_name.to_string()))?;
-        handlebars
-            .render_template_to_write("service_overview", self, &mut w)
-            .chain_err(|| ErrorKind::TemplateError(template_name.to_string()))?;
+            .render_to_write(template_name, self, w)
+            .map_err(|e| {
+                warn!("Could not render template '{}': {}", template_file, e);
+                Error::template(template_name, e)
+            })?;
+        metrics::observe_render("services", start.elapsed());
 
         Ok(())
     }
 }
 
+fn group<'a>(services: &[Service<'a>], catalog: &Catalog, config: &Config) -> Vec<Group<'a>> {
+    if config.present.grouping.by == GroupBy::None {
+        return Vec::new();
+    }
+
+    let mut groups: BTreeMap<String, Vec<Service>> = BTreeMap::new();
+    let mut other = Vec::new();
+    for service in services {
+        let names = group_names(service.name, catalog, config);
+        if names.is_empty() {
+            other.push(service.clone());
+        }
+        for name in names {
+            groups.entry(name).or_default().push(service.clone());
+        }
+    }
+
+    let mut groups: Vec<_> = groups
+        .into_iter()
+        .map(|(name, services)| Group { name, services })
+        .collect();
+    if !other.is_empty() {
+        groups.push(Group {
+            name: OTHER_GROUP.to_string(),
+            services: other,
+        });
+    }
+
+    groups
+}
+
+fn group_names(service_name: &str, catalog: &Catalog, config: &Config) -> BTreeSet<String> {
+    let grouping = &config.present.grouping;
+    let nodes = catalog.nodes_by_service(service_name).unwrap_or_default();
+
+    match grouping.by {
+        GroupBy::None => BTreeSet::new(),
+        GroupBy::TagPrefix => {
+            let prefix = grouping.tag_prefix.as_deref().unwrap_or_default();
+            catalog
+                .service_tags(service_name)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|tag| tag.strip_prefix(prefix))
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect()
+        }
+        GroupBy::Meta => match grouping.meta_key {
+            Some(ref key) => nodes
+                .iter()
+                .filter_map(|node| node.service_meta.get(key))
+                .cloned()
+                .collect(),
+            None => BTreeSet::new(),
+        },
+        GroupBy::Datacenter => nodes.iter().filter_map(|node| node.datacenter.clone()).collect(),
+        GroupBy::Groups => config
+            .groups
+            .iter()
+            .filter(|&(_, services)| services.iter().any(|x| x == service_name))
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    }
+}
+
+/// Merges the `[service_info]` of a service with the service meta data of its nodes; configured
+/// values take precedence.
+fn service_info(config: &Config, service_name: &str, nodes: &[&consul::Node]) -> ServiceInfo {
+    let configured = config.service_info.get(service_name);
+    let pick = |value: fn(&ServiceInfo) -> &Option<String>, key: &str| {
+        configured
+            .and_then(|x| value(x).clone())
+            .or_else(|| nodes.iter().filter_map(|node| node.service_meta.get(key)).next().cloned())
+    };
+
+    ServiceInfo {
+        display_name: pick(|x| &x.display_name, "display_name"),
+        description: pick(|x| &x.description, "description"),
+        icon: pick(|x| &x.icon, "icon"),
+        team: pick(|x| &x.team, "team"),
+        runbook_url: pick(|x| &x.runbook_url, "runbook_url"),
+        docs_url: pick(|x| &x.docs_url, "docs_url"),
+    }
+}
+
+/// Creates a Handlebars registry with all helpers available to templates registered.
+pub(crate) fn handlebars() -> Handlebars<'static> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_helper("len", Box::new(handlebars_helper::vec_len_formatter));
+    handlebars.register_helper("lower", Box::new(handlebars_helper::lower));
+    handlebars.register_helper("urlencode", Box::new(handlebars_helper::urlencode));
+
+    handlebars
+}
+
 mod handlebars_helper {
     use handlebars::{Context, Handlebars, Helper, HelperResult, RenderContext, Output};
 
+    handlebars_helper!(lower: |s: str| s.to_lowercase());
+
+    // Percent-encodes everything but RFC 3986 unreserved characters, e.g., for query parameters
+    handlebars_helper!(urlencode: |s: str| {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect::<String>()
+    });
+
     pub fn vec_len_formatter(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
         let vec_len = if let Some(param) = h.param(0) {
             if let Some(v) = param.value().as_array() {
@@ -108,76 +502,264 @@ mod handlebars_helper {
     }
 }
 
-pub fn gen_index_html(config: &Config, w: &mut dyn Write) -> Result<()> {
+/// Renders the start page; link templates and infos of services the user may not see are left
+/// out.
+pub fn gen_index_html(config: &Config, visibility: &Visibility, w: &mut dyn Write) -> Result<()> {
     let template_name = "index";
 
-    let template_filename = config.present.templates.get(template_name).ok_or_else(|| {
-        ErrorKind::TemplateNotSet(template_name.to_string())
-    })?;
-    // TODO: Let me be a path
-    let template_file = format!("{}/{}", &config.present.template_dir, template_filename);
+    let template_file = template_file(config, template_name)?;
 
-    let mut handlebars = Handlebars::new();
+    let start = Instant::now();
+    let mut handlebars = handlebars();
+    register_template_file(&mut handlebars, template_name, &template_file)?;
+    let data = Index::from_config(config, visibility);
     handlebars
-        .register_template_file(template_name, template_file)
-        .chain_err(|| ErrorKind::TemplateError(template_name.to_string()))?;
-    handlebars.render_template_to_write("index", config, w).chain_err(|| {
-        ErrorKind::TemplateError(template_name.to_string())
-    })?;
+        .render_to_write(template_name, &data, w)
+        .map_err(|e| {
+            warn!("Could not render template '{}': {}", template_file, e);
+            Error::template(template_name, e)
+        })?;
+    metrics::observe_render(template_name, start.elapsed());
 
     Ok(())
 }
 
-pub fn gen_services_html(config: &Config, consul: &Consul, w: &mut dyn Write) -> Result<()> {
+pub fn gen_services_html(
+    config: &Config,
+    consul: &Consul,
+    query: &Query,
+    visibility: &Visibility,
+    w: &mut dyn Write,
+) -> Result<()> {
     let template_name = "services";
 
+    let template_file = template_file(config, template_name)?;
+
+    let catalog = consul.catalog()?;
+    let services = Services::from_catalog(&catalog, config, query, visibility)?;
+
+    services.render(&template_file, w)
+}
+
+/// Renders the services page for a single service; fails with `UnknownService` if the service
+/// does not exist or the user may not see it.
+pub fn gen_service_html(
+    config: &Config,
+    consul: &Consul,
+    service_name: &str,
+    visibility: &Visibility,
+    w: &mut dyn Write,
+) -> Result<()> {
+    let template_file = template_file(config, "services")?;
+
+    let catalog = consul.catalog()?;
+    let query = Query::default();
+    let mut services = Services::from_catalog(&catalog, config, &query, visibility)?;
+    services.services.retain(|service| service.name == service_name);
+    if services.services.is_empty() {
+        return Err(Error::UnknownService {
+            name: service_name.to_string(),
+        });
+    }
+    services.groups.clear();
+
+    services.render(&template_file, w)
+}
+
+/// Context of the error page.
+#[derive(Debug, Serialize)]
+pub struct ErrorPage<'a> {
+    pub project_name: &'a str,
+    pub status: u16,
+    pub reason: &'a str,
+    pub message: &'a str,
+    /// Messages of the error and its causes; empty unless `present.debug` is set.
+    pub chain: Vec<String>,
+}
+
+/// Renders the optional `error` template.
+pub fn gen_error_html(config: &Config, page: &ErrorPage, w: &mut dyn Write) -> Result<()> {
+    let template_name = "error";
+    let template_file = template_file(config, template_name)?;
+
+    let mut handlebars = handlebars();
+    register_template_file(&mut handlebars, template_name, &template_file)?;
+    handlebars
+        .render_to_write(template_name, page, w)
+        .map_err(|e| {
+            warn!("Could not render template '{}': {}", template_file, e);
+            Error::template(template_name, e)
+        })?;
+
+    Ok(())
+}
+
+/// Registers the template in `template_file` as `template_name`; the file is compiled only if it
+/// changed since it was last used.
+fn register_template_file(handlebars: &mut Handlebars, template_name: &str, template_file: &str) -> Result<()> {
+    let load_error = |e: TemplateFileError| {
+        warn!("Could not load template '{}': {}", template_file, e);
+        Error::template(template_name, file_error(e))
+    };
+    let io_error = |e: io::Error| load_error(TemplateFileError::IOError(e, template_name.to_string()));
+    let metadata = fs::metadata(template_file).map_err(io_error)?;
+    let modified = metadata.modified().map_err(io_error)?;
+    let key = (template_name.to_string(), template_file.to_string());
+
+    let mut templates = TEMPLATES.lock().unwrap_or_else(|e| e.into_inner());
+    match templates.get(&key) {
+        Some(cached) if cached.modified == modified && cached.len == metadata.len() => {
+            metrics::observe_template_cache(true);
+        }
+        _ => {
+            metrics::observe_template_cache(false);
+            let source = fs::read_to_string(template_file).map_err(io_error)?;
+            let template = Template::compile_with_name(source, template_name.to_string(), true)
+                .map_err(|e| load_error(e.into()))?;
+            templates.insert(
+                key.clone(),
+                CachedTemplate {
+                    modified,
+                    len: metadata.len(),
+                    template,
+                },
+            );
+        }
+    }
+    handlebars.register_template(template_name, templates[&key].template.clone());
+
+    Ok(())
+}
+
+fn template_file(config: &Config, template_name: &str) -> Result<String> {
     let template_filename = config.present.templates.get(template_name).ok_or_else(|| {
-        ErrorKind::TemplateNotSet(template_name.to_string())
+        Error::TemplateNotSet {
+            name: template_name.to_string(),
+        }
     })?;
+
     // TODO: Let me be a path
-    let template_file = format!("{}/{}", &config.present.template_dir, template_filename);
+    Ok(format!("{}/{}", &config.present.template_dir, template_filename))
+}
 
-    let catalog = consul.catalog().chain_err(|| {
-        ErrorKind::TemplateError(template_name.to_string())
-    })?;
-    let services = Services::from_catalog(&catalog, config)?;
+/// Renders the link templates configured in `[services]` for a node of the given service.
+pub fn generate_service_urls(
+    config: &Config,
+    service_name: &str,
+    node: &consul::Node,
+) -> Result<HashMap<String, String>> {
+    let links = generate_service_links(config, service_name, node)?;
 
-    services.render(&template_file, w)
+    Ok(links.into_iter().map(|link| (link.name, link.url)).collect())
 }
 
-fn generate_service_ulrs(
+/// Renders the link templates configured in `[services]` whose condition matches a node of the
+/// given service.
+///
+/// Templates see the node as returned by Consul, e.g., `Node`, `Address`, `ServicePort`,
+/// `ServiceTags`, `ServiceMeta`, and `Datacenter`, as well as the `[general]` section as
+/// `General`. Rendered URLs are not HTML escaped.
+pub fn generate_service_links(
     config: &Config,
     service_name: &str,
     node: &consul::Node,
-) -> Result<HashMap<String, String>> {
-    let mut m = HashMap::new();
-    if let Some(services) = config.services.get(service_name) {
-        let mut handlebars = Handlebars::new();
-
-        for service in services {
-            let template_name = format!("service_url-{}", service.name);
-            handlebars
-                .register_template_string(&template_name, &service.url)
-                .chain_err(|| ErrorKind::TemplateError(template_name.to_string()))?;
-            let rendered_url = handlebars.render(&template_name, node).chain_err(|| {
-                ErrorKind::TemplateError(template_name.to_string())
-            })?;
-            m.insert(service.name.to_string(), rendered_url);
-        }
+) -> Result<Vec<Link>> {
+    let services = match config.services.get(service_name) {
+        Some(services) => services,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut context = serde_json::to_value(node).map_err(|e| {
+        Error::template(service_name, RenderError::from(e))
+    })?;
+    if let Value::Object(ref mut context) = context {
+        let general = serde_json::to_value(&config.general).map_err(|e| {
+            Error::template(service_name, RenderError::from(e))
+        })?;
+        context.insert("General".to_string(), general);
     }
-    Ok(m)
+
+    let mut handlebars = handlebars();
+    handlebars.register_escape_fn(handlebars::no_escape);
+
+    let mut links = Vec::new();
+    for service in services.iter().filter(|x| matches(&x.when, node)) {
+        let template_name = format!("service_url-{}", service.name);
+        handlebars
+            .register_template_string(&template_name, &service.url)
+            .map_err(|e| Error::template(&template_name, e))?;
+        let url = handlebars.render(&template_name, &context).map_err(|e| {
+            Error::template(&template_name, e)
+        })?;
+        links.push(Link {
+            name: service.name.to_string(),
+            url,
+            icon: service.icon.clone(),
+            description: service.description.clone(),
+            target: match service.target {
+                Target::Same => "_self",
+                Target::New => "_blank",
+            },
+        });
+    }
+
+    Ok(links)
 }
 
-error_chain! {
-    errors {
-        TemplateNotSet(name: String) {
-            description("Template not set")
-            display("Template not set '{}'", name)
-        }
+fn matches(condition: &Condition, node: &consul::Node) -> bool {
+    condition.tags.iter().all(|tag| node.service_tags.contains(tag))
+        && condition
+            .meta
+            .iter()
+            .all(|(key, value)| node.service_meta.get(key) == Some(value))
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Template not set '{name}'")]
+    TemplateNotSet { name: String },
+
+    #[error("Failed to render template '{name}'")]
+    Template {
+        name: String,
+        /// Line of the template the error occurred in, if known.
+        line: Option<usize>,
+        source: Box<TemplateRenderError>,
+    },
 
-        TemplateError(name: String) {
-            description("Failed to render template")
-            display("Failed to render template '{}'", name)
+    #[error("Invalid value '{value}' for query parameter '{param}'")]
+    InvalidQuery { param: String, value: String },
+
+    #[error("Unknown service '{name}'")]
+    UnknownService { name: String },
+
+    #[error("Failed to get catalog from Consul")]
+    Consul(#[from] consul::Error),
+}
+
+impl Error {
+    fn template<E: Into<TemplateRenderError>>(name: &str, source: E) -> Error {
+        let source = source.into();
+        let line = match source {
+            TemplateRenderError::TemplateError(ref e) => e.line_no,
+            TemplateRenderError::RenderError(ref e) => e.line_no,
+            TemplateRenderError::IOError(..) => None,
+        };
+
+        Error::Template {
+            name: name.to_string(),
+            line,
+            source: Box::new(source),
         }
     }
 }
+
+fn file_error(e: TemplateFileError) -> TemplateRenderError {
+    match e {
+        TemplateFileError::TemplateError(e) => e.into(),
+        TemplateFileError::IOError(e, name) => TemplateRenderError::IOError(e, name),
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
@@ -0,0 +1,71 @@
+use consul::Consul;
+use config::Config;
+use std::error::Error;
+use std::iter;
+use std::time::Duration;
+use validate;
+
+/// Outcome of the checks whether sw-present is able to serve its pages.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    /// Details like the Consul leader or template errors; left out for unauthenticated callers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Readiness {
+    /// Removes the messages as they may reveal internals like Consul URLs or file paths.
+    pub fn without_messages(mut self) -> Readiness {
+        for check in &mut self.checks {
+            check.message = None;
+        }
+        self
+    }
+}
+
+/// Checks that Consul has a leader and answers within `timeout` and that the page templates
+/// are readable and compile.
+pub fn check(config: &Config, consul: &Consul, timeout: Duration) -> Readiness {
+    let consul_check = match consul.leader(timeout) {
+        Ok(leader) => Check {
+            name: "consul",
+            ok: true,
+            message: Some(format!("leader is {}", leader)),
+        },
+        Err(e) => Check {
+            name: "consul",
+            ok: false,
+            message: Some(
+                iter::successors(Some(&e as &dyn Error), |&x| x.source())
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(": "),
+            ),
+        },
+    };
+
+    let problems = validate::validate_template_files(config);
+    let templates_check = Check {
+        name: "templates",
+        ok: problems.is_empty(),
+        message: Some(if problems.is_empty() {
+            "templates compile".to_string()
+        } else {
+            problems.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("; ")
+        }),
+    };
+
+    let checks = vec![consul_check, templates_check];
+    Readiness {
+        ready: checks.iter().all(|x| x.ok),
+        checks,
+    }
+}
@@ -0,0 +1,214 @@
+use auth::IpRange;
+use bcrypt;
+use config::{self, AuthMethod, Config, ConfigFile, Consul, GroupBy};
+use discover;
+use handlebars::{Template, TemplateError};
+use hyper::Uri;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A single finding of `validate`; `location` is the config key or file the problem refers to.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Checks the effective `config` loaded from `files` for problems that would only surface at
+/// runtime, e.g., unparsable Consul URLs, missing or broken templates, and ignored keys.
+pub fn validate(config: &Config, files: &[ConfigFile]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for file in files {
+        match Config::unknown_keys(file) {
+            Ok(keys) => {
+                for key in keys {
+                    problems.push(problem(file, format!("unknown key '{}'", key)));
+                }
+            }
+            Err(e) => problems.push(problem(file, e)),
+        }
+    }
+    for name in config::unknown_env_vars() {
+        problems.push(problem(&name, "unknown environment variable"));
+    }
+
+    validate_consul(&mut problems, "consul", &config.consul);
+    let mut profiles: Vec<_> = config.profiles.iter().collect();
+    profiles.sort_by_key(|&(name, _)| name);
+    for (name, profile) in profiles {
+        if let Some(ref consul) = profile.consul {
+            validate_consul(&mut problems, &format!("profiles.{}.consul", name), consul);
+        }
+    }
+
+    validate_templates(&mut problems, config);
+
+    let mut services: Vec<_> = config.services.iter().collect();
+    services.sort_by_key(|&(name, _)| name);
+    for (service_name, services) in services {
+        for service in services {
+            if let Err(e) = Template::compile(&service.url) {
+                let location = format!("services.{}.{}.url", service_name, service.name);
+                problems.push(problem(&location, format!("does not compile at {}", template_error(&e))));
+            }
+        }
+    }
+
+    validate_grouping(&mut problems, config);
+    validate_auth(&mut problems, config);
+    validate_access(&mut problems, config);
+
+    if let Some(ref output) = config.discover.output {
+        if !discover::OUTPUT_MODULES.contains(&output.as_str()) {
+            problems.push(problem(&"discover.output", format!("unknown output module '{}'", output)));
+        }
+    }
+
+    problems
+}
+
+fn validate_consul(problems: &mut Vec<Problem>, location: &str, consul: &Consul) {
+    let location = format!("{}.urls", location);
+    if consul.urls.is_empty() {
+        problems.push(problem(&location, "no URL configured"));
+    }
+    for url in &consul.urls {
+        match url.parse::<Uri>() {
+            Ok(ref uri) if uri.scheme_part().is_none() || uri.authority_part().is_none() => {
+                problems.push(problem(&location, format!("URL '{}' lacks scheme or host", url)))
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(problem(&location, format!("invalid URL '{}': {}", url, e))),
+        }
+    }
+}
+
+fn validate_grouping(problems: &mut Vec<Problem>, config: &Config) {
+    let grouping = &config.present.grouping;
+    match grouping.by {
+        GroupBy::Meta if grouping.meta_key.is_none() => {
+            problems.push(problem(&"present.grouping.meta_key", "required to group by meta data"))
+        }
+        GroupBy::Groups if config.groups.is_empty() => {
+            problems.push(problem(&"groups", "required to group by explicit groups"))
+        }
+        _ => {}
+    }
+}
+
+fn validate_auth(problems: &mut Vec<Problem>, config: &Config) {
+    let auth = &config.auth;
+    match auth.method {
+        AuthMethod::Basic if auth.basic.users.is_empty() => {
+            problems.push(problem(&"auth.basic.users", "required for basic authentication"))
+        }
+        AuthMethod::Proxy if auth.proxy.trusted_proxies.is_empty() => {
+            problems.push(problem(&"auth.proxy.trusted_proxies", "required for proxy authentication"))
+        }
+        AuthMethod::Oidc if auth.oidc.is_none() => {
+            problems.push(problem(&"auth.oidc", "required for OpenID Connect authentication"))
+        }
+        _ => {}
+    }
+
+    for user in &auth.basic.users {
+        if user.password_hash.parse::<bcrypt::HashParts>().is_err() {
+            let location = format!("auth.basic.users.{}", user.name);
+            problems.push(problem(&location, "password_hash is not a bcrypt hash"));
+        }
+    }
+    for range in &auth.proxy.trusted_proxies {
+        if let Err(e) = range.parse::<IpRange>() {
+            problems.push(problem(&"auth.proxy.trusted_proxies", e));
+        }
+    }
+    if let Some(ref oidc) = auth.oidc {
+        for &(key, url) in &[("issuer", &oidc.issuer), ("redirect_url", &oidc.redirect_url)] {
+            match url.parse::<Uri>() {
+                Ok(ref uri) if uri.scheme_part().is_some() && uri.authority_part().is_some() => {}
+                _ => problems.push(problem(&format!("auth.oidc.{}", key), format!("invalid URL '{}'", url))),
+            }
+        }
+    }
+}
+
+fn validate_access(problems: &mut Vec<Problem>, config: &Config) {
+    let access = &config.access;
+    let mut users: Vec<_> = access.users.iter().collect();
+    users.sort_by_key(|&(name, _)| name);
+    for (user, groups) in users {
+        for group in groups {
+            if !access.groups.contains_key(group) {
+                problems.push(problem(&format!("access.users.{}", user), format!("unknown group '{}'", group)));
+            }
+        }
+    }
+    if !access.users.is_empty() && access.groups.is_empty() {
+        problems.push(problem(&"access.groups", "required to restrict access"));
+    }
+}
+
+/// Checks only the page templates, e.g., whether sw-present is ready to serve pages.
+pub fn validate_template_files(config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    validate_templates(&mut problems, config);
+
+    problems
+}
+
+fn validate_templates(problems: &mut Vec<Problem>, config: &Config) {
+    let template_dir = Path::new(&config.present.template_dir);
+    if !template_dir.is_dir() {
+        problems.push(problem(
+            &"present.template_dir",
+            format!("directory '{}' does not exist", template_dir.display()),
+        ));
+        return;
+    }
+
+    for name in &["index", "services"] {
+        if !config.present.templates.contains_key(*name) {
+            problems.push(problem(&format!("present.templates.{}", name), "template not set"));
+        }
+    }
+
+    let mut templates: Vec<_> = config.present.templates.iter().collect();
+    templates.sort();
+    for (name, file_name) in templates {
+        let location = format!("present.templates.{}", name);
+        let file = template_dir.join(file_name);
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(e) => {
+                problems.push(problem(&location, format!("could not read '{}': {}", file.display(), e)));
+                continue;
+            }
+        };
+        if let Err(e) = Template::compile(&source) {
+            let message = format!("'{}' does not compile at {}", file.display(), template_error(&e));
+            problems.push(problem(&location, message));
+        }
+    }
+}
+
+fn template_error(e: &TemplateError) -> String {
+    match (e.line_no, e.column_no) {
+        (Some(line), Some(column)) => format!("line {}, column {}: {}", line, column, e.reason),
+        _ => e.reason.to_string(),
+    }
+}
+
+fn problem<L: fmt::Display, M: fmt::Display>(location: &L, message: M) -> Problem {
+    Problem {
+        location: location.to_string(),
+        message: message.to_string(),
+    }
+}
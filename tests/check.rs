@@ -0,0 +1,109 @@
+extern crate serde_json;
+extern crate service_world;
+
+use service_world::check::{Status, Summary};
+use service_world::config::{Checks, MinPassing, NoCritical};
+use service_world::consul::Catalog;
+
+fn catalog() -> Catalog {
+    serde_json::from_str(include_str!("fixtures/catalog.json")).unwrap()
+}
+
+fn catalog_with_critical_checks(critical_checks: serde_json::Value) -> Catalog {
+    let mut catalog: serde_json::Value = serde_json::from_str(include_str!("fixtures/catalog.json")).unwrap();
+    catalog["critical_checks"] = critical_checks;
+
+    serde_json::from_value(catalog).unwrap()
+}
+
+fn no_critical(tag: &str) -> Checks {
+    Checks {
+        min_passing: Vec::new(),
+        no_critical: vec![NoCritical { tag: tag.to_string() }],
+    }
+}
+
+#[test]
+fn min_passing_perf_data_uses_range_thresholds() {
+    let checks = Checks {
+        min_passing: vec![MinPassing {
+            service: "web".to_string(),
+            critical: 1,
+            warning: Some(3),
+        }],
+        no_critical: vec![NoCritical { tag: "prod".to_string() }],
+    };
+
+    let summary = Summary::evaluate(&catalog(), &checks);
+
+    assert_eq!(summary.status, Status::Critical);
+    assert_eq!(
+        summary.to_string(),
+        "CHECK CRITICAL - web has 1 passing instances, tag prod has critical instances in web (1) \
+         | 'web_passing'=1;3:;1:;0; 'prod_critical'=1;;0;0;"
+    );
+}
+
+#[test]
+fn no_critical_ignores_instances_with_warnings() {
+    // node-2 is not passing for web, but none of its checks is critical
+    let catalog = catalog_with_critical_checks(serde_json::json!([]));
+
+    let summary = Summary::evaluate(&catalog, &no_critical("prod"));
+
+    assert_eq!(summary.status, Status::Ok);
+    assert_eq!(
+        summary.to_string(),
+        "CHECK OK - tag prod has no critical instances | 'prod_critical'=0;;0;0;"
+    );
+}
+
+#[test]
+fn no_critical_counts_critical_node_checks_for_all_services_of_the_node() {
+    let catalog = catalog_with_critical_checks(serde_json::json!([
+        {"Node": "node-3", "CheckID": "serfHealth", "Status": "critical", "ServiceName": ""}
+    ]));
+
+    let summary = Summary::evaluate(&catalog, &no_critical("prod"));
+
+    assert_eq!(summary.status, Status::Critical);
+    assert_eq!(
+        summary.to_string(),
+        "CHECK CRITICAL - tag prod has critical instances in db (1) | 'prod_critical'=1;;0;0;"
+    );
+}
+
+#[test]
+fn no_critical_ignores_critical_checks_of_other_services() {
+    let catalog = catalog_with_critical_checks(serde_json::json!([
+        {"Node": "node-1", "CheckID": "service:cache", "Status": "critical", "ServiceName": "cache"}
+    ]));
+
+    let summary = Summary::evaluate(&catalog, &no_critical("prod"));
+
+    assert_eq!(summary.status, Status::Ok);
+}
+
+#[test]
+fn min_passing_is_warning_between_thresholds() {
+    let checks = Checks {
+        min_passing: vec![MinPassing {
+            service: "web".to_string(),
+            critical: 1,
+            warning: Some(2),
+        }],
+        no_critical: Vec::new(),
+    };
+
+    let summary = Summary::evaluate(&catalog(), &checks);
+
+    assert_eq!(summary.status, Status::Warning);
+}
+
+#[test]
+fn checks_without_rules_are_unknown() {
+    let summary = Summary::evaluate(&catalog(), &Checks::default());
+
+    assert_eq!(summary.status, Status::Unknown);
+    assert_eq!(summary.to_string(), "CHECK UNKNOWN - no checks defined");
+}
@@ -0,0 +1,106 @@
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate service_world;
+extern crate toml;
+
+use service_world::config::{self, Config, Format, OidcAuth, Profile};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn example() -> Config {
+    let mut config = Config::from_file_as(Path::new("examples/sw-present.conf"), Format::Toml).unwrap();
+    config.auth.oidc = Some(OidcAuth {
+        issuer: "https://idp.example.com".to_string(),
+        client_id: "service-world".to_string(),
+        client_secret: "secret".to_string(),
+        redirect_url: "https://services.example.com/auth/callback".to_string(),
+        scopes: vec!["openid".to_string()],
+        username_claim: "email".to_string(),
+    });
+
+    config
+}
+
+fn reload(content: &str, format: Format) -> Config {
+    let path: PathBuf = env::temp_dir().join(format!("sw-config-{}-{}", std::process::id(), format));
+    fs::write(&path, content).unwrap();
+    let config = Config::from_file_as(&path, format);
+    fs::remove_file(&path).unwrap();
+
+    config.unwrap()
+}
+
+#[test]
+fn toml_round_trip_equals_config() {
+    let config = example();
+    let content = toml::to_string(&toml::Value::try_from(&config).unwrap()).unwrap();
+
+    assert_eq!(reload(&content, Format::Toml), config);
+}
+
+#[test]
+fn yaml_round_trip_equals_config() {
+    let config = example();
+    let content = serde_yaml::to_string(&config).unwrap();
+
+    assert_eq!(reload(&content, Format::Yaml), config);
+}
+
+#[test]
+fn json_round_trip_equals_config() {
+    let config = example();
+    let content = serde_json::to_string_pretty(&config).unwrap();
+
+    assert_eq!(reload(&content, Format::Json), config);
+}
+
+#[test]
+fn redact_replaces_secrets() {
+    let mut config = example();
+    config.consul.token = Some("consul-token".to_string());
+    let mut staging = config.consul.clone();
+    staging.token = Some("staging-token".to_string());
+    config.profiles.insert(
+        "staging".to_string(),
+        Profile {
+            consul: Some(staging),
+            discover: None,
+        },
+    );
+    let mut value = toml::Value::try_from(&config).unwrap();
+
+    config::redact(&mut value);
+
+    assert_eq!(value["consul"]["token"].as_str(), Some(config::REDACTED));
+    assert_eq!(value["profiles"]["staging"]["consul"]["token"].as_str(), Some(config::REDACTED));
+    assert_eq!(value["auth"]["oidc"]["client_secret"].as_str(), Some(config::REDACTED));
+    assert_eq!(value["auth"]["basic"]["users"][0]["password_hash"].as_str(), Some(config::REDACTED));
+    assert_eq!(value["auth"]["basic"]["users"][0]["name"].as_str(), Some("admin"));
+    assert_eq!(value["auth"]["oidc"]["client_id"].as_str(), Some("service-world"));
+    let content = toml::to_string(&value).unwrap();
+    for secret in &["consul-token", "staging-token", "\"secret\"", "'secret'", "$2b$"] {
+        assert!(!content.contains(secret), "{} in {}", secret, content);
+    }
+}
+
+#[test]
+fn redact_keeps_configs_without_secrets() {
+    let mut value = toml::Value::try_from(Config::default()).unwrap();
+    let expected = value.clone();
+
+    config::redact(&mut value);
+
+    assert_eq!(value, expected);
+}
+
+#[test]
+fn only_the_consul_token_is_a_secret_env_var() {
+    let secrets: Vec<_> = config::ENV_OVERRIDES
+        .iter()
+        .filter(|&&(section, key, _)| config::is_secret_env_var(section, key))
+        .map(|&(section, key, _)| config::env_var_name(section, key))
+        .collect();
+
+    assert_eq!(secrets, vec!["SW_CONSUL_TOKEN"]);
+}
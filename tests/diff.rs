@@ -0,0 +1,100 @@
+#[macro_use]
+extern crate serde_json;
+extern crate service_world;
+
+use serde_json::Value;
+use service_world::consul::Catalog;
+use service_world::diff::Diff;
+
+fn fixture() -> Value {
+    serde_json::from_str(include_str!("fixtures/catalog.json")).unwrap()
+}
+
+fn catalog(value: Value) -> Catalog {
+    serde_json::from_value(value).unwrap()
+}
+
+fn node(id: &str, name: &str, service: &str, port: u16) -> Value {
+    json!({
+        "ID": id,
+        "Node": name,
+        "NodeMeta": {},
+        "Address": "10.0.0.4",
+        "ServicePort": port,
+        "ServiceTags": ["prod"],
+        "ServiceID": service,
+        "ServiceName": service,
+        "ServiceMeta": {}
+    })
+}
+
+#[test]
+fn identical_catalogs_have_no_changes() {
+    let diff = Diff::between(&catalog(fixture()), &catalog(fixture()));
+
+    assert!(diff.is_empty());
+}
+
+#[test]
+fn added_services_and_nodes() {
+    let mut new = fixture();
+    new["services"]["cache"] = json!(["prod"]);
+    new["nodes_by_service"]["cache"] = json!([node("d4e5", "node-4", "cache", 6379)]);
+
+    let diff = Diff::between(&catalog(fixture()), &catalog(new));
+
+    assert_eq!(diff.added_services, vec!["cache"]);
+    assert_eq!(diff.added_nodes, vec!["node-4"]);
+    assert!(diff.removed_services.is_empty());
+    assert!(diff.removed_nodes.is_empty());
+    assert!(diff.changed_services.is_empty());
+}
+
+#[test]
+fn removed_services_and_nodes() {
+    let mut new = fixture();
+    new["services"].as_object_mut().unwrap().remove("db");
+    new["nodes_by_service"].as_object_mut().unwrap().remove("db");
+
+    let diff = Diff::between(&catalog(fixture()), &catalog(new));
+
+    assert_eq!(diff.removed_services, vec!["db"]);
+    assert_eq!(diff.removed_nodes, vec!["node-3"]);
+    assert!(diff.added_services.is_empty());
+    assert!(diff.added_nodes.is_empty());
+    assert!(diff.changed_services.is_empty());
+}
+
+#[test]
+fn changed_services() {
+    let mut new = fixture();
+    new["services"]["web"] = json!(["http", "prod", "canary"]);
+    // node-2 is replaced by node-4; node-1 moves to another port and becomes unhealthy
+    new["nodes_by_service"]["web"][0] = node("d4e5", "node-4", "web", 8080);
+    new["nodes_by_service"]["web"][1]["ServicePort"] = json!(8081);
+    new["nodes_by_service"]["web"][1]["ServiceTags"] = json!(["http"]);
+    new["healthy_nodes_by_service"]["web"] = json!([]);
+
+    let diff = Diff::between(&catalog(fixture()), &catalog(new));
+
+    assert_eq!(diff.added_nodes, vec!["node-4"]);
+    assert_eq!(diff.removed_nodes, vec!["node-2"]);
+    assert_eq!(diff.changed_services.len(), 1);
+    let web = &diff.changed_services[0];
+    assert_eq!(web.name, "web");
+    assert_eq!(web.added_tags, vec!["canary"]);
+    assert!(web.removed_tags.is_empty());
+    assert_eq!(web.added_instances.len(), 1);
+    assert_eq!(web.added_instances[0].node, "node-4");
+    assert_eq!(web.removed_instances.len(), 1);
+    assert_eq!(web.removed_instances[0].node, "node-2");
+    assert_eq!(web.changed_instances.len(), 1);
+    let instance = &web.changed_instances[0];
+    assert_eq!(instance.node, "node-1");
+    assert_eq!(instance.service_id, "web-1");
+    assert!(instance.address.is_none());
+    assert_eq!(instance.port.as_ref().map(|x| (x.from, x.to)), Some((8080, 8081)));
+    assert!(instance.added_tags.is_empty());
+    assert_eq!(instance.removed_tags, vec!["prod"]);
+    assert_eq!(instance.healthy.as_ref().map(|x| (x.from, x.to)), Some((true, false)));
+}
@@ -0,0 +1,59 @@
+extern crate serde_json;
+extern crate service_world;
+
+use service_world::consul::Catalog;
+use service_world::discover::{self, Report};
+use std::io::Write;
+
+fn catalog() -> Catalog {
+    serde_json::from_str(include_str!("fixtures/catalog.json")).unwrap()
+}
+
+fn output<F>(write: F) -> String
+where
+    F: Fn(&Report, &mut dyn Write) -> discover::Result<()>,
+{
+    let catalog = catalog();
+    let mut buffer = Vec::new();
+    write(&Report::from_catalog(&catalog), &mut buffer).unwrap();
+
+    String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn json_output_matches_snapshot() {
+    assert_eq!(output(|r, w| r.write_json(w)), include_str!("snapshots/report.json"));
+}
+
+#[test]
+fn yaml_output_matches_snapshot() {
+    assert_eq!(output(|r, w| r.write_yaml(w)), include_str!("snapshots/report.yaml"));
+}
+
+#[test]
+fn csv_output_matches_snapshot() {
+    assert_eq!(output(|r, w| r.write_csv(w)), include_str!("snapshots/report.csv"));
+}
+
+#[test]
+fn tsv_output_matches_snapshot() {
+    assert_eq!(output(|r, w| r.write_tsv(w)), include_str!("snapshots/report.tsv"));
+}
+
+#[test]
+fn reports_of_the_same_catalog_are_identical() {
+    assert_eq!(output(|r, w| r.write_json(w)), output(|r, w| r.write_json(w)));
+}
+
+#[test]
+fn file_sd_output_matches_snapshot() {
+    assert_eq!(output(|r, w| r.write_file_sd(w, false)), include_str!("snapshots/file_sd.json"));
+}
+
+#[test]
+fn healthy_file_sd_output_matches_snapshot() {
+    assert_eq!(
+        output(|r, w| r.write_file_sd(w, true)),
+        include_str!("snapshots/file_sd_healthy.json")
+    );
+}
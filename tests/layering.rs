@@ -0,0 +1,121 @@
+extern crate service_world;
+
+use service_world::config::{Config, ConfigFile, SharedConfig};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Tests share the process environment
+static ENV: Mutex<()> = Mutex::new(());
+
+const CONFIG: &str = r#"
+[consul]
+urls = ["http://file:8500"]
+token = "file-token"
+
+[discover]
+tags = ["prod"]
+
+[profiles.staging.consul]
+urls = ["http://staging:8500"]
+
+[profiles.staging.discover]
+services = ["web"]
+"#;
+
+fn config_file(name: &str) -> ConfigFile {
+    let dir: PathBuf = env::temp_dir().join(format!("sw-layering-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    // Keeps a user config file of the machine running the tests out of the layers
+    env::set_var("XDG_CONFIG_HOME", &dir);
+    let path = dir.join("sw-present.conf");
+    fs::write(&path, CONFIG).unwrap();
+
+    ConfigFile::new(path)
+}
+
+fn load(name: &str, profile: Option<&str>, vars: &[(&str, &str)]) -> Config {
+    let _lock = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let config_file = config_file(name);
+    for &(name, value) in vars {
+        env::set_var(name, value);
+    }
+    let config = Config::load_with_profile(Some(&config_file), profile);
+    for &(name, _) in vars {
+        env::remove_var(name);
+    }
+
+    config.unwrap()
+}
+
+#[test]
+fn environment_overrides_config_file() {
+    let config = load(
+        "env",
+        None,
+        &[("SW_CONSUL_URLS", "http://env-1:8500, http://env-2:8500,"), ("SW_DISCOVER_OUTPUT", "json")],
+    );
+
+    assert_eq!(config.consul.urls, vec!["http://env-1:8500", "http://env-2:8500"]);
+    assert_eq!(config.consul.token.as_deref(), Some("file-token"));
+    assert_eq!(config.discover.output.as_deref(), Some("json"));
+    assert_eq!(config.discover.tags, Some(vec!["prod".to_string()]));
+}
+
+#[test]
+fn profile_replaces_sections_of_config_file() {
+    let config = load("profile", Some("staging"), &[]);
+
+    assert_eq!(config.consul.urls, vec!["http://staging:8500"]);
+    assert_eq!(config.consul.token, None);
+    assert_eq!(config.discover.services, Some(vec!["web".to_string()]));
+    assert_eq!(config.discover.tags, None);
+}
+
+#[test]
+fn environment_overrides_profile() {
+    let config = load(
+        "profile-env",
+        Some("staging"),
+        &[("SW_CONSUL_URLS", "http://env:8500"), ("SW_DISCOVER_TAGS", "canary")],
+    );
+
+    assert_eq!(config.consul.urls, vec!["http://env:8500"]);
+    assert_eq!(config.discover.services, Some(vec!["web".to_string()]));
+    assert_eq!(config.discover.tags, Some(vec!["canary".to_string()]));
+}
+
+#[test]
+fn unknown_profile_is_an_error() {
+    let _lock = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let config_file = config_file("unknown");
+
+    assert!(Config::load_with_profile(Some(&config_file), Some("missing")).is_err());
+}
+
+#[test]
+fn reload_keeps_current_config_if_templates_do_not_compile() {
+    let _lock = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let config_file = config_file("reload");
+    let template_dir = config_file.path.with_file_name("templates");
+    fs::create_dir_all(&template_dir).unwrap();
+    fs::write(template_dir.join("index.html.hbs"), "{{general.project_name}}").unwrap();
+    fs::write(template_dir.join("services.html.hbs"), "{{#if broken}").unwrap();
+    let content = format!(
+        "[general]\nproject_name = \"Reloaded\"\n\
+         [present]\ntemplate_dir = {:?}\n\
+         [present.templates]\nindex = \"index.html.hbs\"\nservices = \"services.html.hbs\"\n",
+        template_dir.display().to_string()
+    );
+    fs::write(&config_file.path, content).unwrap();
+    let shared = SharedConfig::new(Config::default());
+
+    assert!(shared.reload(Some(&config_file)).is_err());
+    assert_eq!(shared.get().general.project_name, Config::default().general.project_name);
+
+    fs::write(template_dir.join("services.html.hbs"), "{{#if services}}{{/if}}").unwrap();
+
+    assert!(shared.reload(Some(&config_file)).is_ok());
+    assert_eq!(shared.get().general.project_name, "Reloaded");
+}
@@ -0,0 +1,39 @@
+extern crate service_world;
+
+use service_world::logging::Timestamp;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn timestamp(secs: u64, millis: u32) -> String {
+    Timestamp(UNIX_EPOCH + Duration::new(secs, millis * 1_000_000)).to_string()
+}
+
+#[test]
+fn epoch() {
+    assert_eq!(timestamp(0, 0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(timestamp(86_399, 999), "1970-01-01T23:59:59.999Z");
+}
+
+#[test]
+fn end_of_year() {
+    assert_eq!(timestamp(1_704_067_199, 0), "2023-12-31T23:59:59.000Z");
+    assert_eq!(timestamp(1_704_067_200, 0), "2024-01-01T00:00:00.000Z");
+}
+
+#[test]
+fn leap_years() {
+    // 2024 is a leap year
+    assert_eq!(timestamp(1_709_164_800, 0), "2024-02-29T00:00:00.000Z");
+    assert_eq!(timestamp(1_709_251_200, 0), "2024-03-01T00:00:00.000Z");
+    assert_eq!(timestamp(1_735_603_200, 0), "2024-12-31T00:00:00.000Z");
+    // 2100 is not as it is divisible by 100 but 2000 is as it is divisible by 400
+    assert_eq!(timestamp(4_107_456_000, 0), "2100-02-28T00:00:00.000Z");
+    assert_eq!(timestamp(4_107_542_400, 0), "2100-03-01T00:00:00.000Z");
+    assert_eq!(timestamp(951_782_400, 0), "2000-02-29T00:00:00.000Z");
+}
+
+#[test]
+fn times_before_the_epoch_are_the_epoch() {
+    let before = Timestamp(UNIX_EPOCH - Duration::from_secs(1)).to_string();
+
+    assert_eq!(before, "1970-01-01T00:00:00.000Z");
+}
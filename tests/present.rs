@@ -0,0 +1,559 @@
+extern crate serde_json;
+extern crate service_world;
+
+use service_world::config::{
+    AccessRule, Condition, Config, GroupBy, Service, ServiceInfo, Target, EVERYONE_GROUP,
+};
+use service_world::consul::{Catalog, Node};
+use service_world::metrics;
+use service_world::present::{self, Health, Index, Query, Services, Sort, Visibility};
+
+fn catalog() -> Catalog {
+    serde_json::from_str(include_str!("fixtures/catalog.json")).unwrap()
+}
+
+/// Returns the fixture catalog with the service meta data of the first node of `service` replaced.
+fn catalog_with_meta(service: &str, meta: serde_json::Value) -> Catalog {
+    let mut catalog: serde_json::Value = serde_json::from_str(include_str!("fixtures/catalog.json")).unwrap();
+    catalog["nodes_by_service"][service][0]["ServiceMeta"] = meta;
+
+    serde_json::from_value(catalog).unwrap()
+}
+
+/// Returns the service info shown for `service` on the services page.
+fn info(catalog: &Catalog, config: &Config, service: &str) -> ServiceInfo {
+    let query = Query::default();
+    let visibility = Visibility::for_user(config, None);
+    let services = Services::from_catalog(catalog, config, &query, &visibility).unwrap();
+
+    services.services.into_iter().find(|x| x.name == service).unwrap().info
+}
+
+/// Returns the names of the groups on the services page with the names of their services.
+fn groups(catalog: &Catalog, config: &Config) -> Vec<(String, Vec<String>)> {
+    let query = Query::default();
+    let visibility = Visibility::for_user(config, None);
+    let services = Services::from_catalog(catalog, config, &query, &visibility).unwrap();
+
+    services
+        .groups
+        .iter()
+        .map(|group| {
+            let names = group.services.iter().map(|x| x.name.to_string()).collect();
+            (group.name.to_string(), names)
+        })
+        .collect()
+}
+
+fn group(name: &str, services: &[&str]) -> (String, Vec<String>) {
+    (name.to_string(), strings(services))
+}
+
+/// Returns the names of the services on the services page for a query.
+fn query(q: Option<&str>, tag: Option<&str>, health: Option<&str>, sort: Option<&str>) -> Vec<String> {
+    let param = |x: Option<&str>| x.map(|x| x.to_string());
+    let catalog = catalog();
+    let config = Config::default();
+    let query = Query::from_params(param(q), param(tag), param(health), param(sort)).unwrap();
+    let visibility = Visibility::for_user(&config, None);
+    let services = Services::from_catalog(&catalog, &config, &query, &visibility).unwrap();
+
+    services.services.iter().map(|x| x.name.to_string()).collect()
+}
+
+fn render_services_page(catalog: &Catalog, config: &Config) -> String {
+    let query = Query::default();
+    let visibility = Visibility::for_user(config, None);
+    let services = Services::from_catalog(catalog, config, &query, &visibility).unwrap();
+    let mut buffer = Vec::new();
+    services.render("examples/templates/services.html.hbs", &mut buffer).unwrap();
+
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Returns the value of a metric without labels.
+fn metric(name: &str) -> u64 {
+    let mut buffer = Vec::new();
+    metrics::write(&mut buffer).unwrap();
+    let prefix = format!("{} ", name);
+    let text = String::from_utf8(buffer).unwrap();
+    let line = text.lines().find(|x| x.starts_with(&prefix)).unwrap();
+
+    line[prefix.len()..].parse().unwrap()
+}
+
+/// Returns the first node of `service` in `catalog`.
+fn node<'a>(catalog: &'a Catalog, service: &str) -> &'a Node {
+    catalog.nodes_by_service(service).unwrap()[0]
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|x| x.to_string()).collect()
+}
+
+fn rule(services: &[&str], tags: &[&str]) -> AccessRule {
+    AccessRule {
+        services: strings(services),
+        tags: strings(tags),
+    }
+}
+
+fn link(name: &str) -> Service {
+    Service {
+        name: name.to_string(),
+        url: "http://{{ Node }}/".to_string(),
+        icon: None,
+        description: None,
+        target: Target::Same,
+        when: Default::default(),
+    }
+}
+
+/// Everyone sees `web`; operators additionally see services tagged `prod`; admins see all.
+fn config() -> Config {
+    let mut config = Config::default();
+    config.access.users.insert("alice".to_string(), strings(&["ops"]));
+    config.access.users.insert("bob".to_string(), strings(&["admins"]));
+    config.access.groups.insert(EVERYONE_GROUP.to_string(), rule(&["web"], &[]));
+    config.access.groups.insert("ops".to_string(), rule(&[], &["prod"]));
+    config.access.groups.insert("admins".to_string(), rule(&["*"], &[]));
+
+    config
+}
+
+#[test]
+fn everyone_sees_all_services_without_access_groups() {
+    let config = Config::default();
+    let visibility = Visibility::for_user(&config, None);
+
+    assert!(visibility.allows::<&str>("db", &[]));
+}
+
+#[test]
+fn anonymous_users_see_services_of_everyone_group() {
+    let config = config();
+    let visibility = Visibility::for_user(&config, None);
+
+    assert!(visibility.allows::<&str>("web", &[]));
+    assert!(!visibility.allows::<&str>("db", &[]));
+    assert!(!visibility.allows("db", &["prod"]));
+}
+
+#[test]
+fn unknown_users_see_services_of_everyone_group() {
+    let config = config();
+    let visibility = Visibility::for_user(&config, Some("mallory"));
+
+    assert!(visibility.allows::<&str>("web", &[]));
+    assert!(!visibility.allows::<&str>("db", &[]));
+}
+
+#[test]
+fn users_see_services_of_their_groups() {
+    let config = config();
+    let alice = Visibility::for_user(&config, Some("alice"));
+    let bob = Visibility::for_user(&config, Some("bob"));
+
+    assert!(alice.allows::<&str>("web", &[]));
+    assert!(alice.allows("db", &["prod"]));
+    assert!(!alice.allows("db", &["dev"]));
+    assert!(bob.allows::<&str>("db", &[]));
+}
+
+#[test]
+fn service_patterns_match_whole_names() {
+    let mut config = Config::default();
+    let patterns = &["web", "haproxy-*", "*-exporter", "elastic*search*-v*"];
+    config.access.groups.insert(EVERYONE_GROUP.to_string(), rule(patterns, &[]));
+    let visibility = Visibility::for_user(&config, None);
+    let allows = |name: &str| visibility.allows::<&str>(name, &[]);
+
+    assert!(allows("web"));
+    assert!(!allows("webserver"));
+    assert!(!allows("my-web"));
+    assert!(allows("haproxy-"));
+    assert!(allows("haproxy-monitor"));
+    assert!(!allows("haproxy"));
+    assert!(allows("node-exporter"));
+    assert!(!allows("node-exporter-v2"));
+    assert!(allows("elasticsearch-v7"));
+    assert!(allows("elastic-log-search-v7"));
+    assert!(!allows("elasticsearch"));
+}
+
+#[test]
+fn services_page_shows_visible_services_only() {
+    let catalog = catalog();
+    let config = config();
+    let query = Query::default();
+    let names = |user| -> Vec<_> {
+        let visibility = Visibility::for_user(&config, user);
+        let services = Services::from_catalog(&catalog, &config, &query, &visibility).unwrap();
+        services.services.iter().map(|x| x.name.to_string()).collect()
+    };
+
+    assert_eq!(names(None), vec!["web"]);
+    assert_eq!(names(Some("alice")), vec!["db", "web"]);
+    assert_eq!(names(Some("bob")), vec!["db", "web"]);
+}
+
+#[test]
+fn index_shows_visible_services_only() {
+    let mut config = config();
+    config.services.insert("web".to_string(), vec![link("default")]);
+    config.services.insert("db".to_string(), vec![link("default")]);
+    config.service_info.insert("db".to_string(), ServiceInfo::default());
+    let visibility = Visibility::for_user(&config, None);
+
+    let index = Index::from_config(&config, &visibility);
+    let json = serde_json::to_value(&index).unwrap();
+
+    assert_eq!(index.services.keys().collect::<Vec<_>>(), vec![&"web"]);
+    assert!(index.service_info.is_empty());
+    let mut keys: Vec<_> = json.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, vec!["index_links", "project_name", "service_info", "services"]);
+}
+
+#[test]
+fn service_links_render_templates_with_node_and_general_context() {
+    let catalog = catalog();
+    let mut config = Config::default();
+    config.general.project_name = "Service World".to_string();
+    config.services.insert(
+        "web".to_string(),
+        vec![Service {
+            name: "status".to_string(),
+            url: "https://{{ lower Node }}:{{ ServicePort }}/status?project={{ urlencode General.project_name }}"
+                .to_string(),
+            icon: Some("info".to_string()),
+            description: Some("Status page".to_string()),
+            target: Target::New,
+            when: Default::default(),
+        }],
+    );
+
+    let links = present::generate_service_links(&config, "web", node(&catalog, "web")).unwrap();
+
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].name, "status");
+    assert_eq!(links[0].url, "https://node-2:8080/status?project=Service%20World");
+    assert_eq!(links[0].icon.as_deref(), Some("info"));
+    assert_eq!(links[0].description.as_deref(), Some("Status page"));
+    assert_eq!(links[0].target, "_blank");
+}
+
+#[test]
+fn service_links_open_in_same_window_by_default() {
+    let catalog = catalog();
+    let mut config = Config::default();
+    config.services.insert("web".to_string(), vec![link("default")]);
+
+    let links = present::generate_service_links(&config, "web", node(&catalog, "web")).unwrap();
+
+    assert_eq!(links[0].url, "http://node-2/");
+    assert_eq!(links[0].description, None);
+    assert_eq!(links[0].target, "_self");
+}
+
+#[test]
+fn service_links_are_rendered_only_if_their_condition_matches() {
+    let catalog = catalog_with_meta("web", serde_json::json!({"env": "prod"}));
+    let when = |tags: &[&str], meta: &[(&str, &str)]| Condition {
+        tags: strings(tags),
+        meta: meta.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect(),
+    };
+    let mut config = Config::default();
+    let links = vec![
+        Service { when: when(&["http"], &[]), ..link("http") },
+        Service { when: when(&["https"], &[]), ..link("https") },
+        Service { when: when(&["http", "prod"], &[("env", "prod")]), ..link("prod") },
+        Service { when: when(&[], &[("env", "dev")]), ..link("dev") },
+    ];
+    config.services.insert("web".to_string(), links);
+    let names = |node: &Node| -> Vec<_> {
+        present::generate_service_links(&config, "web", node)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.name)
+            .collect()
+    };
+    let nodes = catalog.nodes_by_service("web").unwrap();
+
+    assert_eq!(names(nodes[0]), vec!["http", "prod"]);
+    assert_eq!(names(nodes[1]), vec!["http"]);
+}
+
+#[test]
+fn services_without_link_templates_have_no_links() {
+    let catalog = catalog();
+
+    let links = present::generate_service_links(&Config::default(), "db", node(&catalog, "db")).unwrap();
+
+    assert!(links.is_empty());
+}
+
+#[test]
+fn configured_service_info_takes_precedence_over_service_meta() {
+    let catalog = catalog_with_meta(
+        "web",
+        serde_json::json!({"display_name": "Meta Web", "team": "meta-team", "docs_url": "https://docs/"}),
+    );
+    let mut config = Config::default();
+    config.service_info.insert(
+        "web".to_string(),
+        ServiceInfo {
+            display_name: Some("Web Frontend".to_string()),
+            team: Some("frontend".to_string()),
+            ..Default::default()
+        },
+    );
+
+    let info = info(&catalog, &config, "web");
+
+    assert_eq!(info.display_name.as_deref(), Some("Web Frontend"));
+    assert_eq!(info.team.as_deref(), Some("frontend"));
+    // Values missing in the config still fall back to the service meta data
+    assert_eq!(info.docs_url.as_deref(), Some("https://docs/"));
+    assert_eq!(info.description, None);
+}
+
+#[test]
+fn service_info_falls_back_to_service_meta() {
+    let catalog = catalog_with_meta(
+        "db",
+        serde_json::json!({"description": "Primary database", "runbook_url": "https://runbooks/db", "env": "prod"}),
+    );
+
+    let info = info(&catalog, &Config::default(), "db");
+
+    assert_eq!(
+        info,
+        ServiceInfo {
+            description: Some("Primary database".to_string()),
+            runbook_url: Some("https://runbooks/db".to_string()),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn services_are_not_grouped_by_default() {
+    assert!(groups(&catalog(), &Config::default()).is_empty());
+}
+
+#[test]
+fn services_are_grouped_by_tags() {
+    let mut config = Config::default();
+    config.present.grouping.by = GroupBy::TagPrefix;
+
+    assert_eq!(
+        groups(&catalog(), &config),
+        vec![group("http", &["web"]), group("prod", &["db", "web"])]
+    );
+}
+
+#[test]
+fn services_are_grouped_by_tag_prefix() {
+    let mut config = Config::default();
+    config.present.grouping.by = GroupBy::TagPrefix;
+    config.present.grouping.tag_prefix = Some("ht".to_string());
+
+    assert_eq!(
+        groups(&catalog(), &config),
+        vec![group("tp", &["web"]), group(present::OTHER_GROUP, &["db"])]
+    );
+}
+
+#[test]
+fn services_are_grouped_by_service_meta() {
+    let catalog = catalog_with_meta("web", serde_json::json!({"team": "frontend"}));
+    let mut config = Config::default();
+    config.present.grouping.by = GroupBy::Meta;
+    config.present.grouping.meta_key = Some("team".to_string());
+
+    assert_eq!(
+        groups(&catalog, &config),
+        vec![group("frontend", &["web"]), group(present::OTHER_GROUP, &["db"])]
+    );
+}
+
+#[test]
+fn services_are_grouped_by_datacenter() {
+    let mut config = Config::default();
+    config.present.grouping.by = GroupBy::Datacenter;
+
+    assert_eq!(
+        groups(&catalog(), &config),
+        vec![group("dc1", &["web"]), group(present::OTHER_GROUP, &["db"])]
+    );
+}
+
+#[test]
+fn services_are_grouped_by_configured_groups() {
+    let mut config = Config::default();
+    config.present.grouping.by = GroupBy::Groups;
+    config.groups.insert("frontend".to_string(), strings(&["web"]));
+    config.groups.insert("storage".to_string(), strings(&["db", "cache"]));
+    config.groups.insert("all".to_string(), strings(&["db", "web"]));
+
+    assert_eq!(
+        groups(&catalog(), &config),
+        vec![group("all", &["db", "web"]), group("frontend", &["web"]), group("storage", &["db"])]
+    );
+}
+
+#[test]
+fn services_page_links_services_to_their_own_pages_and_anchors() {
+    let html = render_services_page(&catalog(), &Config::default());
+
+    assert!(html.contains(r#"id="service-web""#));
+    assert!(html.contains(r##"href="#service-web""##));
+    assert!(html.contains(r#"href="/services/web""#));
+    assert!(html.contains(r#"href="/services/db""#));
+}
+
+#[test]
+fn grouped_services_page_has_unique_anchors_per_group() {
+    let mut config = Config::default();
+    config.present.grouping.by = GroupBy::TagPrefix;
+
+    let html = render_services_page(&catalog(), &config);
+
+    // web is listed in both groups http and prod
+    assert!(html.contains(r#"id="service-0-web""#));
+    assert!(html.contains(r##"href="#service-0-web""##));
+    assert!(html.contains(r#"id="service-1-web""#));
+    assert!(html.contains(r##"href="#service-1-web""##));
+    assert!(html.contains(r#"id="service-1-db""#));
+    assert!(!html.contains(r#"id="service-web""#));
+}
+
+#[test]
+fn index_links_services_to_their_own_pages() {
+    let mut config = Config::default();
+    config.present.template_dir = "examples/templates".to_string();
+    config.present.templates.insert("index".to_string(), "index.html.hbs".to_string());
+    let web = ServiceInfo {
+        display_name: Some("Web Frontend".to_string()),
+        ..Default::default()
+    };
+    config.service_info.insert("web".to_string(), web);
+    config.service_info.insert("db server".to_string(), ServiceInfo::default());
+    let mut buffer = Vec::new();
+
+    present::gen_index_html(&config, &Visibility::for_user(&config, None), &mut buffer).unwrap();
+    let html = String::from_utf8(buffer).unwrap();
+
+    assert!(html.contains(r#"<a href="/services/web" class="uk-text-uppercase">Web Frontend</a>"#));
+    assert!(html.contains(r#"<a href="/services/db%20server" class="uk-text-uppercase">db server</a>"#));
+    assert!(!html.contains(r##"href="#service-"##));
+}
+
+#[test]
+fn query_parameters_are_parsed() {
+    let query = Query::from_params(
+        Some("web".to_string()),
+        Some("prod".to_string()),
+        Some("failing".to_string()),
+        Some("unhealthy".to_string()),
+    )
+    .unwrap();
+
+    assert_eq!(query.q.as_deref(), Some("web"));
+    assert_eq!(query.tag.as_deref(), Some("prod"));
+    assert_eq!(query.health, Some(Health::Failing));
+    assert_eq!(query.sort, Sort::Unhealthy);
+}
+
+#[test]
+fn empty_query_parameters_are_ignored() {
+    let empty = || Some(String::new());
+
+    let query = Query::from_params(empty(), empty(), empty(), empty()).unwrap();
+
+    assert_eq!(query.q, None);
+    assert_eq!(query.tag, None);
+    assert_eq!(query.health, None);
+    assert_eq!(query.sort, Sort::Name);
+}
+
+#[test]
+fn invalid_query_parameters_are_errors() {
+    let error = |health: &str, sort: &str| {
+        Query::from_params(None, None, Some(health.to_string()), Some(sort.to_string()))
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(error("sick", "name"), "Invalid value 'sick' for query parameter 'health'");
+    assert_eq!(error("passing", "Name"), "Invalid value 'Name' for query parameter 'sort'");
+    match Query::from_params(None, None, None, Some("size".to_string())) {
+        Err(present::Error::InvalidQuery { ref param, ref value }) => {
+            assert_eq!(param, "sort");
+            assert_eq!(value, "size");
+        }
+        _ => panic!("expected invalid query"),
+    }
+}
+
+#[test]
+fn services_are_filtered_by_text_ignoring_case() {
+    assert_eq!(query(None, None, None, None), vec!["db", "web"]);
+    // Service name, tag, and node name
+    assert_eq!(query(Some("WE"), None, None, None), vec!["web"]);
+    assert_eq!(query(Some("Http"), None, None, None), vec!["web"]);
+    assert_eq!(query(Some("node-3"), None, None, None), vec!["db"]);
+    assert_eq!(query(Some("node"), None, None, None), vec!["db", "web"]);
+    assert!(query(Some("cache"), None, None, None).is_empty());
+}
+
+#[test]
+fn services_are_filtered_by_tag() {
+    assert_eq!(query(None, Some("http"), None, None), vec!["web"]);
+    assert_eq!(query(None, Some("prod"), None, None), vec!["db", "web"]);
+    // Tags match exactly
+    assert!(query(None, Some("htt"), None, None).is_empty());
+}
+
+#[test]
+fn services_are_filtered_by_health() {
+    assert_eq!(query(None, None, Some("passing"), None), vec!["db"]);
+    assert_eq!(query(None, None, Some("failing"), None), vec!["web"]);
+}
+
+#[test]
+fn services_are_sorted() {
+    assert_eq!(query(None, None, None, Some("name")), vec!["db", "web"]);
+    assert_eq!(query(None, None, None, Some("nodes")), vec!["web", "db"]);
+    assert_eq!(query(None, None, None, Some("unhealthy")), vec!["web", "db"]);
+}
+
+#[test]
+fn templates_are_compiled_again_only_if_changed() {
+    let template_file = std::env::temp_dir().join(format!("sw-present-cache-{}.hbs", std::process::id()));
+    let template_file = template_file.to_str().unwrap();
+    let catalog = catalog();
+    let config = Config::default();
+    let query = Query::default();
+    let visibility = Visibility::for_user(&config, None);
+    let services = Services::from_catalog(&catalog, &config, &query, &visibility).unwrap();
+    let render = || {
+        let mut buffer = Vec::new();
+        services.render(template_file, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    };
+
+    std::fs::write(template_file, "{{project_name}}").unwrap();
+    assert_eq!(render(), config.general.project_name);
+    let hits = metric("sw_present_template_cache_hits_total");
+    assert_eq!(render(), config.general.project_name);
+    assert!(metric("sw_present_template_cache_hits_total") > hits);
+
+    let misses = metric("sw_present_template_cache_misses_total");
+    std::fs::write(template_file, "{{#each services}}{{name}} {{/each}}").unwrap();
+    assert_eq!(render(), "db web ");
+    assert!(metric("sw_present_template_cache_misses_total") > misses);
+
+    std::fs::remove_file(template_file).unwrap();
+}